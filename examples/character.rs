@@ -1,36 +1,48 @@
 use bluearchive::spine::*;
+use bluearchive::story::Manifest;
 use macroquad::prelude::*;
 
 #[macroquad::main("Character")]
 async fn main() {
-    let character_path = "arona";
-    let path = format!(
-        "/home/kina/temp/code/rust/spine/data/{}/{}",
-        character_path, character_path
-    );
-    let texture_path = format!("{}_spr.png", path);
+    let manifest = Manifest::load("assets/manifest.yaml")
+        .unwrap_or_else(|e| panic!("Can not load manifest. {:?}", e));
+    // 可在命令行指定角色名，默认显示仓库中自带的桐藤渚
+    let name = std::env::args().nth(1).unwrap_or("桐藤渚".to_string());
+    let entry = manifest
+        .character(&name)
+        .unwrap_or_else(|| panic!("Character not found in manifest."));
+    let texture_path = manifest.resolve(&entry.texture);
 
     let texture = load_texture(&texture_path)
         .await
         .unwrap_or_else(|e| panic!("Can not load character texture. {:?}", e));
-    let atlas_path = format!("{}_spr.atlas", path);
-    let skel_path = format!("{}_spr.skel", path);
+    let atlas_path = manifest.resolve(&entry.atlas);
+    let skel_path = manifest.resolve(&entry.skel);
 
     let spine_demo = SpineDemo::new(
         atlas_path,
-        SkeletonPath::Binary(skel_path),
-        "00".to_string(),
+        SkeletonPath::detect(skel_path),
+        entry.animation.clone(),
     );
+    let spine_demo = match &entry.idle {
+        Some(idle) => spine_demo.with_track(1, idle),
+        None => spine_demo.without_tracks(),
+    };
 
     let mut last_frame = get_time();
-    let mut spine = Spine::load(spine_demo);
+    let mut spine = Spine::load(spine_demo).unwrap_or_else(|e| panic!("{}", e));
+    spine.scale = entry.scale;
     loop {
         let current_time = get_time();
         let delta_time = (current_time - last_frame) as f32;
         last_frame = current_time;
         spine.controller.update(delta_time);
 
-        let meshs = spine.get_mesh(texture.clone(), screen_width() / 2., screen_height());
+        let meshs = spine.get_mesh(
+            texture.clone(),
+            screen_width() / 2. + entry.offset.0,
+            screen_height() + entry.offset.1,
+        );
         for i in &meshs {
             draw_mesh(i);
         }
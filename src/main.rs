@@ -1,45 +1,70 @@
-use std::panic;
-
-use anyhow::Ok;
+use anyhow::Result;
 use bluearchive::story::input::handle_input;
 use bluearchive::story::*;
-use macroquad::{
-    audio::{PlaySoundParams, play_sound},
-    prelude::*,
-};
+use macroquad::prelude::*;
+
+const MANIFEST_PATH: &str = "assets/manifest.yaml";
+
+/// 显示启动失败的原因，直到玩家按 Esc 退出
+async fn show_fatal_error(error: anyhow::Error) -> Result<()> {
+    let message = format!("{:#}", error);
+    println!("Error: {}", message);
+    loop {
+        ui::draw_fatal_error(&message, None);
+        if is_key_pressed(KeyCode::Escape) {
+            break;
+        }
+        next_frame().await;
+    }
+    Err(error)
+}
 
 #[macroquad::main("GalGame")]
 async fn main() -> Result<()> {
     // 初始化游戏状态
-    let mut state = load_resource().await?;
+    let manifest = match Manifest::load(MANIFEST_PATH) {
+        Ok(manifest) => manifest,
+        Err(e) => return show_fatal_error(e.into()).await,
+    };
+    let mut loader = match ResourceLoader::new(&manifest) {
+        Ok(loader) => loader,
+        Err(e) => return show_fatal_error(e.into()).await,
+    };
+    // 每帧载入一项资源，载入画面在两项之间照常刷新
+    while !loader.is_finished() {
+        ui::draw_loading_screen(&loader.progress);
+        next_frame().await;
+        loader.step(&manifest).await;
+    }
+    let mut state = match loader.finish(&manifest) {
+        Ok(state) => state,
+        Err(e) => return show_fatal_error(e.into()).await,
+    };
 
     let mut last_frame = get_time();
     let material = ui::draw_chioce_material();
     state.material = Some(&material);
-    if let Some(sound) = &state.sound {
-        play_sound(
-            sound,
-            PlaySoundParams {
-                looped: true,
-                volume: 1.0,
-            },
-        );
-    }
-    // 游戏主循环
-    while !state.story_ended {
+    // 游戏主循环，出错时显示提示而不是退出
+    while !state.engine.story_ended {
         let current_time = get_time();
         let delta_time = (current_time - last_frame) as f32;
         last_frame = current_time;
-        if let Some(character) = state.speak_state.name.as_ref() {
+        // 先载入新 knot 用到的资源，再处理本帧的演出
+        stream_assets(&mut state, &manifest).await;
+        state.process_events();
+        if let Err(e) = state.update(delta_time) {
+            state.errors.push(e);
+        }
+        if let Err(e) =
             state
                 .character_manager
-                .update(character, delta_time, screen_width() / 2., screen_height())
-                .unwrap_or_else(|e| panic!("Can not update character. {:?}", e));
-            if let Some(animation) = &state.speak_state.animation {
-                state.character_manager.set_animation(character, animation);
-            }
+                .update_stage(delta_time, VIRTUAL_WIDTH, VIRTUAL_HEIGHT)
+        {
+            state.errors.push(e);
+        }
+        if let Err(e) = handle_input(&mut state) {
+            state.errors.push(e);
         }
-        handle_input(&mut state)?;
         draw_frame(&state).await;
         // clear_background(WHITE);
 
@@ -48,6 +73,9 @@ async fn main() -> Result<()> {
         next_frame().await;
     }
 
+    // 故事结束时立即写回已读记录，中途关闭窗口时最多丢失最后几秒的记录
+    state.read_lines.flush();
+
     // 故事结束后的显示循环
     loop {
         draw_frame(&state).await;
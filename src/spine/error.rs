@@ -0,0 +1,55 @@
+use std::fmt;
+
+/// 载入与操作 Spine 骨骼时的错误
+#[derive(Debug)]
+pub enum SpineError {
+    /// atlas 文件缺失或无法解析
+    Atlas { path: String, message: String },
+    /// 骨骼文件缺失、格式不符或版本不兼容
+    Skeleton { path: String, message: String },
+    /// 骨骼中没有该动画
+    Animation {
+        animation: String,
+        track: usize,
+        message: String,
+    },
+    /// 骨骼中没有该皮肤
+    Skin { skin: String, message: String },
+    /// 插槽或附件不存在
+    Attachment {
+        slot: String,
+        attachment: Option<String>,
+    },
+}
+
+impl fmt::Display for SpineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpineError::Atlas { path, message } => {
+                write!(f, "Can not load atlas {}. {}", path, message)
+            }
+            SpineError::Skeleton { path, message } => {
+                write!(f, "Can not read skeleton {}. {}", path, message)
+            }
+            SpineError::Animation {
+                animation,
+                track,
+                message,
+            } => write!(
+                f,
+                "Can not play {} on track {}. {}",
+                animation, track, message
+            ),
+            SpineError::Skin { skin, message } => {
+                write!(f, "Can not set skin {}. {}", skin, message)
+            }
+            SpineError::Attachment { slot, attachment } => write!(
+                f,
+                "Can not set attachment {:?} on slot {}.",
+                attachment, slot
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SpineError {}
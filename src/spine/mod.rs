@@ -1,3 +1,5 @@
+mod error;
 mod spine;
 
+pub use error::*;
 pub use spine::*;
@@ -1,15 +1,18 @@
 use std::sync::Arc;
 
-use anyhow::Result;
 use macroquad::prelude::*;
 use rusty_spine::{
     AnimationStateData, Atlas, SkeletonBinary, SkeletonJson, controller::SkeletonController,
 };
 
+use crate::spine::SpineError;
+
 pub struct SpineDemo {
     atlas_path: String,
     skeleton_path: SkeletonPath,
     animation: String,
+    /// 除 0 号轨道外启动时循环播放的动画，(轨道, 动画名)
+    tracks: Vec<(usize, String)>,
 }
 
 pub enum SkeletonPath {
@@ -17,37 +20,86 @@ pub enum SkeletonPath {
     Binary(String),
 }
 
+impl SkeletonPath {
+    /// 按扩展名判断骨骼格式，扩展名不明确时看文件是否以 `{` 开头
+    pub fn detect(path: String) -> Self {
+        let extension = std::path::Path::new(&path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        match extension.as_deref() {
+            Some("json") => SkeletonPath::Json(path),
+            Some("skel") => SkeletonPath::Binary(path),
+            _ => {
+                let is_json = std::fs::read(&path)
+                    .ok()
+                    .and_then(|bytes| bytes.into_iter().find(|b| !b.is_ascii_whitespace()))
+                    == Some(b'{');
+                if is_json {
+                    SkeletonPath::Json(path)
+                } else {
+                    SkeletonPath::Binary(path)
+                }
+            }
+        }
+    }
+}
+
 impl SpineDemo {
     pub fn new(atlas: String, skel: SkeletonPath, ani: String) -> Self {
         Self {
             atlas_path: atlas,
             skeleton_path: skel,
             animation: ani,
+            tracks: vec![(1, "Idle_01".to_string())],
         }
     }
+
+    /// 在 `track` 上额外循环播放 `animation`，同一轨道只保留最后一次设置
+    pub fn with_track(mut self, track: usize, animation: &str) -> Self {
+        self.tracks.retain(|(t, _)| *t != track);
+        self.tracks.push((track, animation.to_string()));
+        self
+    }
+
+    /// 只播放 0 号轨道
+    pub fn without_tracks(mut self) -> Self {
+        self.tracks.clear();
+        self
+    }
 }
 
+/// 骨骼坐标到画布像素的默认缩放，按 1920x1080 虚拟画布调整
+pub const DEFAULT_SPINE_SCALE: f32 = 0.4;
+
 #[derive(Debug)]
 pub struct Spine {
     pub controller: SkeletonController,
+    pub scale: f32,
+    /// 切换动画时默认的混合秒数
+    pub default_mix: f32,
 }
 
 impl Spine {
-    pub fn load(info: SpineDemo) -> Self {
-        let atlas = Arc::new(Atlas::new_from_file(info.atlas_path).unwrap());
-        let skeleton_data = Arc::new(match info.skeleton_path {
-            SkeletonPath::Binary(path) => {
-                let skeleton_binary = SkeletonBinary::new(atlas);
-                skeleton_binary
-                    .read_skeleton_data_file(path)
-                    .unwrap_or_else(|_| panic!("Read skeleton failed"))
-            }
-            SkeletonPath::Json(path) => {
-                let skeleton_json = SkeletonJson::new(atlas);
-                skeleton_json
-                    .read_skeleton_data_file(path)
-                    .unwrap_or_else(|_| panic!("Read skeleton failed"))
-            }
+    pub fn load(info: SpineDemo) -> Result<Self, SpineError> {
+        let atlas =
+            Arc::new(
+                Atlas::new_from_file(&info.atlas_path).map_err(|e| SpineError::Atlas {
+                    path: info.atlas_path.clone(),
+                    message: format!("{:?}", e),
+                })?,
+            );
+        let skeleton_error = |path: &str, e| SpineError::Skeleton {
+            path: path.to_string(),
+            message: format!("{:?}", e),
+        };
+        let skeleton_data = Arc::new(match &info.skeleton_path {
+            SkeletonPath::Binary(path) => SkeletonBinary::new(atlas)
+                .read_skeleton_data_file(path)
+                .map_err(|e| skeleton_error(path, e))?,
+            SkeletonPath::Json(path) => SkeletonJson::new(atlas)
+                .read_skeleton_data_file(path)
+                .map_err(|e| skeleton_error(path, e))?,
         });
         let animation_state_data = Arc::new(AnimationStateData::new(skeleton_data.clone()));
         let animation_series: Vec<String> = skeleton_data
@@ -59,17 +111,33 @@ impl Spine {
             .collect();
         debug!("Founded animation {:?}", animation_series);
         let mut controller = SkeletonController::new(skeleton_data, animation_state_data);
-        controller
+        if controller
             .animation_state
             .set_animation_by_name(0, &info.animation, true)
-            .unwrap();
-        controller
-            .animation_state
-            .set_animation_by_name(1, "Idle_01", true)
-            .unwrap();
-        Self {
-            controller: controller,
+            .is_err()
+        {
+            println!(
+                "Animation {} not found, track 0 left empty.",
+                info.animation
+            );
         }
+        for (track, animation) in &info.tracks {
+            if controller
+                .animation_state
+                .set_animation_by_name(*track, animation, true)
+                .is_err()
+            {
+                println!(
+                    "Animation {} not found, track {} left empty.",
+                    animation, track
+                );
+            }
+        }
+        Ok(Self {
+            controller: controller,
+            scale: DEFAULT_SPINE_SCALE,
+            default_mix: 0.2,
+        })
     }
     pub fn get_mesh(&mut self, texture: Texture2D, x: f32, y: f32) -> Vec<Mesh> {
         let mut meshs: Vec<Mesh> = Vec::new();
@@ -78,8 +146,8 @@ impl Spine {
             let mut vertices = Vec::new();
             for index in 0..renderable.vertices.len() {
                 vertices.push(Vertex::new(
-                    renderable.vertices[index][0] * 0.4 + x,
-                    -renderable.vertices[index][1] * 0.4 + y,
+                    renderable.vertices[index][0] * self.scale + x,
+                    -renderable.vertices[index][1] * self.scale + y,
                     0.0,
                     renderable.uvs[index][0],
                     renderable.uvs[index][1],
@@ -102,10 +170,95 @@ impl Spine {
         }
         meshs
     }
-    pub fn set_animationn(&mut self, animation_name: &str, index: usize) -> Result<()> {
+    pub fn set_animationn(&mut self, animation_name: &str, index: usize) -> Result<(), SpineError> {
+        self.set_animation(index, animation_name, true, None)
+    }
+
+    /// 立即切换 `track` 上的动画，`mix` 为 `None` 时使用 `default_mix`
+    pub fn set_animation(
+        &mut self,
+        track: usize,
+        animation: &str,
+        looping: bool,
+        mix: Option<f32>,
+    ) -> Result<(), SpineError> {
+        let mut entry = self
+            .controller
+            .animation_state
+            .set_animation_by_name(track, animation, looping)
+            .map_err(|e| animation_error(animation, track, e))?;
+        entry.set_mix_duration(mix.unwrap_or(self.default_mix));
+        Ok(())
+    }
+
+    /// 在 `track` 当前动画之后排队播放，`delay` 为相对上一个动画开始的秒数，0 表示上一个结束时
+    pub fn add_animation(
+        &mut self,
+        track: usize,
+        animation: &str,
+        looping: bool,
+        delay: f32,
+        mix: Option<f32>,
+    ) -> Result<(), SpineError> {
+        let mut entry = self
+            .controller
+            .animation_state
+            .add_animation_by_name(track, animation, looping, delay)
+            .map_err(|e| animation_error(animation, track, e))?;
+        entry.set_mix_duration(mix.unwrap_or(self.default_mix));
+        Ok(())
+    }
+
+    /// 在 `track` 当前动画结束后淡出并清空
+    pub fn queue_clear_track(&mut self, track: usize, mix: Option<f32>) {
+        self.controller.animation_state.add_empty_animation(
+            track,
+            mix.unwrap_or(self.default_mix),
+            0.0,
+        );
+    }
+
+    /// 淡出并清空 `track`
+    pub fn clear_track(&mut self, track: usize, mix: Option<f32>) {
         self.controller
             .animation_state
-            .set_animation_by_name(index, animation_name, true)?;
+            .set_empty_animation(track, mix.unwrap_or(self.default_mix));
+    }
+
+    pub fn set_skin(&mut self, skin: &str) -> Result<(), SpineError> {
+        self.controller
+            .skeleton
+            .set_skin_by_name(skin)
+            .map_err(|e| SpineError::Skin {
+                skin: skin.to_string(),
+                message: format!("{:?}", e),
+            })?;
+        // 换皮后让各插槽重新取用新皮肤的附件
+        self.controller.skeleton.set_slots_to_setup_pose();
         Ok(())
     }
+
+    /// 设置插槽显示的附件，`None` 隐藏该插槽
+    pub fn set_attachment(
+        &mut self,
+        slot: &str,
+        attachment: Option<&str>,
+    ) -> Result<(), SpineError> {
+        if self.controller.skeleton.set_attachment(slot, attachment) {
+            Ok(())
+        } else {
+            Err(SpineError::Attachment {
+                slot: slot.to_string(),
+                attachment: attachment.map(str::to_string),
+            })
+        }
+    }
+}
+
+fn animation_error(animation: &str, track: usize, error: impl std::fmt::Debug) -> SpineError {
+    SpineError::Animation {
+        animation: animation.to_string(),
+        track,
+        message: format!("{:?}", error),
+    }
 }
@@ -0,0 +1,543 @@
+use macroquad::audio::{
+    PlaySoundParams, Sound, load_sound_from_bytes, play_sound, set_sound_volume, stop_sound,
+};
+use macroquad::file::load_file;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::story::{StoryError, StoryResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioChannel {
+    Bgm,
+    Sfx,
+    Voice,
+    Ambience,
+}
+
+/// 各声道音量，`master` 作用于全部声道
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VolumeSettings {
+    pub master: f32,
+    pub bgm: f32,
+    pub sfx: f32,
+    pub voice: f32,
+    pub ambience: f32,
+    /// 播放语音时 BGM 压低到的比例
+    pub duck: f32,
+}
+
+impl Default for VolumeSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            bgm: 0.8,
+            sfx: 1.0,
+            voice: 1.0,
+            ambience: 0.8,
+            duck: 0.4,
+        }
+    }
+}
+
+/// 正在播放的循环音轨，`level` 为淡入淡出的进度
+struct Track {
+    name: String,
+    channel: AudioChannel,
+    sound: Sound,
+    level: f32,
+    target: f32,
+    /// 每秒变化量，0 表示立即到达
+    speed: f32,
+}
+
+impl Track {
+    fn new(name: &str, sound: &Sound, channel: AudioChannel, fade: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            channel,
+            sound: sound.clone(),
+            level: if fade > 0.0 { 0.0 } else { 1.0 },
+            target: 1.0,
+            speed: fade_speed(fade),
+        }
+    }
+
+    fn fade_in(&mut self, fade: f32) {
+        self.target = 1.0;
+        self.speed = fade_speed(fade);
+        if self.speed == 0.0 {
+            self.level = 1.0;
+        }
+    }
+
+    fn fade_out(&mut self, fade: f32) {
+        self.target = 0.0;
+        self.speed = fade_speed(fade);
+        if self.speed == 0.0 {
+            self.level = 0.0;
+        }
+    }
+
+    fn step(&mut self, delta_time: f32) {
+        if self.speed == 0.0 {
+            self.level = self.target;
+        } else if self.level < self.target {
+            self.level = (self.level + self.speed * delta_time).min(self.target);
+        } else {
+            self.level = (self.level - self.speed * delta_time).max(self.target);
+        }
+    }
+}
+
+fn fade_speed(fade: f32) -> f32 {
+    if fade > 0.0 { 1.0 / fade } else { 0.0 }
+}
+
+/// BGM / 音效 / 语音 / 环境音四个声道的播放管理
+/// macroquad 无法暂停或查询播放进度：暂停以静音实现，语音是否播放完根据文件时长估算
+pub struct AudioManager {
+    sounds: HashMap<String, Sound>,
+    durations: HashMap<String, f32>,
+    /// 各音频文件的字节数，用于估算占用
+    sizes: HashMap<String, usize>,
+    pub volumes: VolumeSettings,
+    bgm: Option<Track>,
+    ambience: Option<Track>,
+    /// 正在淡出的旧音轨
+    fading: Vec<Track>,
+    voice: Option<(String, Sound)>,
+    /// 语音目录下存在子目录的角色
+    voiced: HashSet<String>,
+    /// 语音目录中的文件，以 `<角色>/<文件名>` 为名，用到时才载入
+    voice_files: HashMap<String, String>,
+    voice_remaining: f32,
+    /// BGM 当前的压低比例
+    duck_level: f32,
+    paused: bool,
+}
+
+impl AudioManager {
+    pub fn new() -> Self {
+        Self {
+            sounds: HashMap::new(),
+            durations: HashMap::new(),
+            sizes: HashMap::new(),
+            volumes: VolumeSettings::default(),
+            bgm: None,
+            ambience: None,
+            fading: Vec::new(),
+            voice: None,
+            voiced: HashSet::new(),
+            voice_files: HashMap::new(),
+            voice_remaining: 0.0,
+            duck_level: 1.0,
+            paused: false,
+        }
+    }
+
+    pub async fn load(&mut self, name: &str, path: &str) -> StoryResult<()> {
+        let sound_error = |e| StoryError::Sound {
+            path: path.to_string(),
+            message: format!("{:?}", e),
+        };
+        let bytes = load_file(path).await.map_err(sound_error)?;
+        let sound = load_sound_from_bytes(&bytes).await.map_err(sound_error)?;
+        if let Some(duration) = sound_duration(&bytes) {
+            self.durations.insert(name.to_string(), duration);
+        }
+        self.sizes.insert(name.to_string(), bytes.len());
+        self.sounds.insert(name.to_string(), sound);
+        Ok(())
+    }
+
+    /// 释放未在播放的音频，正在播放或淡出时返回 `false`
+    pub fn unload(&mut self, name: &str) -> bool {
+        let playing = self
+            .bgm
+            .iter()
+            .chain(&self.ambience)
+            .chain(&self.fading)
+            .any(|track| track.name == name)
+            || self.voice.as_ref().is_some_and(|(voice, _)| voice == name);
+        if playing {
+            return false;
+        }
+        self.durations.remove(name);
+        self.sizes.remove(name);
+        self.sounds.remove(name).is_some()
+    }
+
+    /// 音频文件的字节数，未载入时为 0
+    pub fn memory(&self, name: &str) -> usize {
+        self.sizes.get(name).copied().unwrap_or(0)
+    }
+
+    /// 记下 `dir/<角色>/*.ogg|wav`，以 `<角色>/<文件名>` 为名，返回文件数量；
+    /// 只扫描目录不解码，语音由分段载入按 knot 载入
+    pub fn index_voices(&mut self, dir: &str) -> StoryResult<usize> {
+        let read_dir = |path: &Path| {
+            fs::read_dir(path).map_err(|e| StoryError::io(&path.to_string_lossy(), e))
+        };
+        let dir = Path::new(dir);
+        if !dir.is_dir() {
+            return Ok(0);
+        }
+        let mut count = 0;
+        for speaker in read_dir(dir)?.flatten() {
+            let speaker = speaker.path();
+            let Some(speaker_name) = speaker.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if !speaker.is_dir() {
+                continue;
+            }
+            self.voiced.insert(speaker_name.to_string());
+            for file in read_dir(&speaker)?.flatten() {
+                let file = file.path();
+                let is_audio = matches!(
+                    file.extension().and_then(|e| e.to_str()),
+                    Some("ogg" | "wav")
+                );
+                let Some(stem) = file.file_stem().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                if !is_audio {
+                    continue;
+                }
+                let name = format!("{}/{}", speaker_name, stem);
+                self.voice_files
+                    .insert(name, file.to_string_lossy().to_string());
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// 语音文件的路径，语音目录中没有该文件时为 `None`
+    pub fn voice_path(&self, name: &str) -> Option<&str> {
+        self.voice_files.get(name).map(String::as_str)
+    }
+
+    /// 该角色是否有语音目录，没有的角色按约定查找语音失败时不必报告
+    pub fn has_voices(&self, speaker: &str) -> bool {
+        self.voiced.contains(speaker)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.sounds.contains_key(name)
+    }
+
+    pub fn duration(&self, name: &str) -> Option<f32> {
+        self.durations.get(name).copied()
+    }
+
+    fn channel_volume(&self, channel: AudioChannel) -> f32 {
+        let volume = match channel {
+            AudioChannel::Bgm => self.volumes.bgm,
+            AudioChannel::Sfx => self.volumes.sfx,
+            AudioChannel::Voice => self.volumes.voice,
+            AudioChannel::Ambience => self.volumes.ambience,
+        };
+        if self.paused {
+            0.0
+        } else {
+            volume * self.volumes.master
+        }
+    }
+
+    pub fn set_volume(&mut self, channel: AudioChannel, volume: f32) {
+        let volume = volume.clamp(0.0, 1.0);
+        match channel {
+            AudioChannel::Bgm => self.volumes.bgm = volume,
+            AudioChannel::Sfx => self.volumes.sfx = volume,
+            AudioChannel::Voice => self.volumes.voice = volume,
+            AudioChannel::Ambience => self.volumes.ambience = volume,
+        }
+        self.apply_volumes();
+    }
+
+    pub fn current_bgm(&self) -> Option<&str> {
+        self.bgm.as_ref().map(|track| track.name.as_str())
+    }
+
+    pub fn current_ambience(&self) -> Option<&str> {
+        self.ambience.as_ref().map(|track| track.name.as_str())
+    }
+
+    /// 切换 BGM，旧曲目在 `fade` 秒内淡出、新曲目同时淡入；`None` 表示停止
+    pub fn play_bgm(&mut self, name: Option<&str>, fade: f32) {
+        if name.is_some() && name == self.current_bgm() {
+            return;
+        }
+        let next = self.start_track(name, fade, AudioChannel::Bgm);
+        let previous = std::mem::replace(&mut self.bgm, next);
+        self.retire(previous, fade);
+    }
+
+    pub fn play_ambience(&mut self, name: Option<&str>, fade: f32) {
+        if name.is_some() && name == self.current_ambience() {
+            return;
+        }
+        let next = self.start_track(name, fade, AudioChannel::Ambience);
+        let previous = std::mem::replace(&mut self.ambience, next);
+        self.retire(previous, fade);
+    }
+
+    fn start_track(
+        &mut self,
+        name: Option<&str>,
+        fade: f32,
+        channel: AudioChannel,
+    ) -> Option<Track> {
+        let name = name?;
+        // 淡出中的同名音轨与缓存共用一个 Sound，再播放一次的话，淡出结束时的
+        // stop_sound 会连新的播放一起停掉，所以把它取回来重新淡入
+        if let Some(index) = self.fading.iter().position(|track| track.name == name) {
+            let mut track = self.fading.remove(index);
+            track.channel = channel;
+            track.fade_in(fade);
+            return Some(track);
+        }
+        let Some(sound) = self.sounds.get(name) else {
+            println!("Warning: sound {} not loaded.", name);
+            return None;
+        };
+        let track = Track::new(name, sound, channel, fade);
+        play_sound(
+            sound,
+            PlaySoundParams {
+                looped: true,
+                volume: self.track_volume(&track),
+            },
+        );
+        Some(track)
+    }
+
+    fn retire(&mut self, track: Option<Track>, fade: f32) {
+        if let Some(mut track) = track {
+            track.fade_out(fade);
+            self.fading.push(track);
+        }
+    }
+
+    fn track_volume(&self, track: &Track) -> f32 {
+        let duck = if track.channel == AudioChannel::Bgm {
+            self.duck_level
+        } else {
+            1.0
+        };
+        track.level * duck * self.channel_volume(track.channel)
+    }
+
+    pub fn play_sfx(&mut self, name: &str) {
+        if let Some(sound) = self.sounds.get(name) {
+            play_sound(
+                sound,
+                PlaySoundParams {
+                    looped: false,
+                    volume: self.channel_volume(AudioChannel::Sfx),
+                },
+            );
+        } else {
+            println!("Warning: sound {} not loaded.", name);
+        }
+    }
+
+    /// 播放语音，同时只保留一条；时长未知时按 3 秒估算
+    pub fn play_voice(&mut self, name: &str) -> bool {
+        self.stop_voice();
+        let Some(sound) = self.sounds.get(name) else {
+            return false;
+        };
+        play_sound(
+            sound,
+            PlaySoundParams {
+                looped: false,
+                volume: self.channel_volume(AudioChannel::Voice),
+            },
+        );
+        self.voice = Some((name.to_string(), sound.clone()));
+        self.voice_remaining = self.duration(name).unwrap_or(3.0);
+        true
+    }
+
+    pub fn stop_voice(&mut self) {
+        if let Some((_, sound)) = self.voice.take() {
+            stop_sound(&sound);
+        }
+        self.voice_remaining = 0.0;
+    }
+
+    pub fn is_voice_playing(&self) -> bool {
+        self.voice.is_some()
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.apply_volumes();
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.apply_volumes();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn stop_all(&mut self) {
+        for track in self
+            .bgm
+            .take()
+            .into_iter()
+            .chain(self.ambience.take())
+            .chain(self.fading.drain(..))
+        {
+            stop_sound(&track.sound);
+        }
+        self.stop_voice();
+    }
+
+    /// 每帧推进淡入淡出和语音计时
+    pub fn update(&mut self, delta_time: f32) {
+        if self.voice.is_some() {
+            self.voice_remaining -= delta_time;
+            if self.voice_remaining <= 0.0 {
+                self.voice = None;
+                self.voice_remaining = 0.0;
+            }
+        }
+        let duck_target = if self.voice.is_some() {
+            self.volumes.duck
+        } else {
+            1.0
+        };
+        // 压低和恢复都在 0.3 秒内完成
+        let step = delta_time / 0.3;
+        self.duck_level = if self.duck_level < duck_target {
+            (self.duck_level + step).min(duck_target)
+        } else {
+            (self.duck_level - step).max(duck_target)
+        };
+
+        for track in self
+            .bgm
+            .iter_mut()
+            .chain(self.ambience.iter_mut())
+            .chain(self.fading.iter_mut())
+        {
+            track.step(delta_time);
+        }
+        self.fading.retain(|track| {
+            let finished = track.level <= 0.0;
+            if finished {
+                stop_sound(&track.sound);
+            }
+            !finished
+        });
+        self.apply_volumes();
+    }
+
+    fn apply_volumes(&self) {
+        for track in self.bgm.iter().chain(&self.ambience).chain(&self.fading) {
+            set_sound_volume(&track.sound, self.track_volume(track));
+        }
+        if let Some((_, sound)) = &self.voice {
+            set_sound_volume(sound, self.channel_volume(AudioChannel::Voice));
+        }
+    }
+}
+
+impl Default for AudioManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 从文件头估算时长（秒），支持 Ogg Vorbis 与 WAV
+pub fn sound_duration(bytes: &[u8]) -> Option<f32> {
+    fn find(bytes: &[u8], pattern: &[u8]) -> Option<usize> {
+        bytes.windows(pattern.len()).position(|w| w == pattern)
+    }
+    fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+    }
+
+    if bytes.starts_with(b"OggS") {
+        // 识别头中的采样率，以及最后一页的 granule position（总采样数）
+        let header = find(bytes, b"\x01vorbis")?;
+        let sample_rate = read_u32(bytes, header + 12)?;
+        let last_page = bytes.windows(4).rposition(|w| w == b"OggS")?;
+        let granule =
+            u64::from_le_bytes(bytes.get(last_page + 6..last_page + 14)?.try_into().ok()?);
+        if sample_rate == 0 {
+            return None;
+        }
+        return Some(granule as f32 / sample_rate as f32);
+    }
+    if bytes.starts_with(b"RIFF") {
+        let fmt = find(bytes, b"fmt ")?;
+        let byte_rate = read_u32(bytes, fmt + 16)?;
+        let data = find(bytes, b"data")?;
+        let size = read_u32(bytes, data + 4)?;
+        if byte_rate == 0 {
+            return None;
+        }
+        return Some(size as f32 / byte_rate as f32);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(byte_rate: u32, size: u32) -> Vec<u8> {
+        let mut bytes = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0\x01\0\x02\0".to_vec();
+        bytes.extend(44100u32.to_le_bytes());
+        bytes.extend(byte_rate.to_le_bytes());
+        bytes.extend(b"\x04\0\x10\0data");
+        bytes.extend(size.to_le_bytes());
+        bytes
+    }
+
+    fn ogg(sample_rate: u32, granule: u64) -> Vec<u8> {
+        let mut bytes = b"OggS".to_vec();
+        bytes.extend([0; 24]);
+        bytes.extend(b"\x01vorbis");
+        bytes.extend(0u32.to_le_bytes());
+        bytes.push(2);
+        bytes.extend(sample_rate.to_le_bytes());
+        bytes.extend([0; 16]);
+        bytes.extend(b"OggS\0\x04");
+        bytes.extend(granule.to_le_bytes());
+        bytes.extend([0; 12]);
+        bytes
+    }
+
+    #[test]
+    fn wav_duration_from_byte_rate() {
+        assert_eq!(sound_duration(&wav(176400, 352800)), Some(2.0));
+        assert_eq!(sound_duration(&wav(0, 352800)), None);
+    }
+
+    #[test]
+    fn ogg_duration_from_last_granule() {
+        assert_eq!(sound_duration(&ogg(48000, 72000)), Some(1.5));
+        assert_eq!(sound_duration(&ogg(0, 72000)), None);
+    }
+
+    #[test]
+    fn unknown_or_truncated_files_have_no_duration() {
+        assert_eq!(sound_duration(b""), None);
+        assert_eq!(sound_duration(b"ID3\x04"), None);
+        assert_eq!(sound_duration(&wav(176400, 352800)[..30]), None);
+        let ogg = ogg(48000, 72000);
+        assert_eq!(sound_duration(&ogg[..ogg.len() - 20]), None);
+    }
+}
@@ -0,0 +1,137 @@
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::story::VIRTUAL_HEIGHT;
+
+/// 背景图缩放到窗口的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackgroundFit {
+    /// 等比放大铺满窗口，多出的部分裁掉
+    #[default]
+    Cover,
+    /// 等比缩放完整显示，留黑边
+    Contain,
+    /// 拉伸到窗口大小
+    Stretch,
+    /// 按虚拟分辨率 1920x1080 下的原始尺寸居中
+    Fixed,
+}
+
+impl BackgroundFit {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "cover" => Some(Self::Cover),
+            "contain" => Some(Self::Contain),
+            "stretch" => Some(Self::Stretch),
+            "fixed" => Some(Self::Fixed),
+            _ => None,
+        }
+    }
+
+    /// 纹理在屏幕上的目标矩形，居中放置
+    pub fn dest_rect(&self, texture: Vec2, screen: Vec2) -> Rect {
+        let size = match self {
+            Self::Stretch => return Rect::new(0.0, 0.0, screen.x, screen.y),
+            Self::Cover => texture * (screen.x / texture.x).max(screen.y / texture.y),
+            Self::Contain => texture * (screen.x / texture.x).min(screen.y / texture.y),
+            Self::Fixed => texture * (screen.y / VIRTUAL_HEIGHT),
+        };
+        Rect::new(
+            (screen.x - size.x) / 2.0,
+            (screen.y - size.y) / 2.0,
+            size.x,
+            size.y,
+        )
+    }
+}
+
+/// 镜头的缩放与平移，`pan` 以屏幕尺寸为单位
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Framing {
+    pub zoom: f32,
+    pub pan: (f32, f32),
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            pan: (0.0, 0.0),
+        }
+    }
+}
+
+impl Framing {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            zoom: self.zoom + (other.zoom - self.zoom) * t,
+            pan: (
+                self.pan.0 + (other.pan.0 - self.pan.0) * t,
+                self.pan.1 + (other.pan.1 - self.pan.1) * t,
+            ),
+        }
+    }
+}
+
+/// Ken Burns 效果：在 `duration` 秒内从 `from` 缓慢推移到 `to`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KenBurns {
+    pub from: Framing,
+    pub to: Framing,
+    pub duration: f32,
+}
+
+/// 当前背景的显示方式
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct BackgroundView {
+    pub fit: BackgroundFit,
+    pub ken_burns: Option<KenBurns>,
+    pub elapsed: f32,
+}
+
+impl BackgroundView {
+    pub fn new(fit: BackgroundFit, ken_burns: Option<KenBurns>) -> Self {
+        Self {
+            fit,
+            ken_burns,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        if self.ken_burns.is_some() {
+            self.elapsed += delta_time;
+        }
+    }
+
+    pub fn framing(&self) -> Framing {
+        match &self.ken_burns {
+            Some(kb) => {
+                let t = if kb.duration > 0.0 {
+                    (self.elapsed / kb.duration).clamp(0.0, 1.0)
+                } else {
+                    1.0
+                };
+                // 缓入缓出，起止时不突兀
+                let t = t * t * (3.0 - 2.0 * t);
+                kb.from.lerp(&kb.to, t)
+            }
+            None => Framing::default(),
+        }
+    }
+
+    /// 应用缩放模式与镜头后的目标矩形
+    pub fn dest_rect(&self, texture: Vec2, screen: Vec2) -> Rect {
+        let rect = self.fit.dest_rect(texture, screen);
+        let framing = self.framing();
+        let size = rect.size() * framing.zoom;
+        let center = rect.center() + vec2(framing.pan.0 * screen.x, framing.pan.1 * screen.y);
+        Rect::new(
+            center.x - size.x / 2.0,
+            center.y - size.y / 2.0,
+            size.x,
+            size.y,
+        )
+    }
+}
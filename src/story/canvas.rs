@@ -0,0 +1,53 @@
+use macroquad::prelude::*;
+
+/// 虚拟画布尺寸，全部界面与演出都按此分辨率布局
+pub const VIRTUAL_WIDTH: f32 = 1920.0;
+pub const VIRTUAL_HEIGHT: f32 = 1080.0;
+
+/// 画布在窗口中的位置（逻辑像素），等比缩放并居中，多出的部分留黑边
+pub fn canvas_viewport() -> Rect {
+    let scale = (screen_width() / VIRTUAL_WIDTH).min(screen_height() / VIRTUAL_HEIGHT);
+    let width = VIRTUAL_WIDTH * scale;
+    let height = VIRTUAL_HEIGHT * scale;
+    Rect::new(
+        (screen_width() - width) / 2.0,
+        (screen_height() - height) / 2.0,
+        width,
+        height,
+    )
+}
+
+pub fn canvas_camera() -> Camera2D {
+    let viewport = canvas_viewport();
+    // viewport 以物理像素计，高 DPI 屏幕上需要乘以缩放系数
+    let dpi = screen_dpi_scale();
+    Camera2D {
+        viewport: Some((
+            (viewport.x * dpi) as i32,
+            (viewport.y * dpi) as i32,
+            (viewport.w * dpi) as i32,
+            (viewport.h * dpi) as i32,
+        )),
+        ..Camera2D::from_display_rect(Rect::new(0.0, 0.0, VIRTUAL_WIDTH, VIRTUAL_HEIGHT))
+    }
+}
+
+/// 清屏并切换到画布坐标，绘制结束后调用 [`end_canvas`]
+pub fn begin_canvas() {
+    clear_background(BLACK);
+    set_camera(&canvas_camera());
+}
+
+pub fn end_canvas() {
+    set_default_camera();
+}
+
+/// 窗口坐标转换为画布坐标
+pub fn screen_to_canvas(point: Vec2) -> Vec2 {
+    let viewport = canvas_viewport();
+    (point - viewport.point()) * (VIRTUAL_WIDTH / viewport.w)
+}
+
+pub fn canvas_mouse_position() -> Vec2 {
+    screen_to_canvas(Vec2::from(mouse_position()))
+}
@@ -1,26 +1,371 @@
-use crate::spine::{Spine, SpineDemo};
+use crate::spine::{Spine, SpineDemo, SpineError};
+use crate::story::{
+    Easing, Motion, MotionKind, MotionSpec, Pose, StoryError, StoryResult, VIRTUAL_HEIGHT,
+    VIRTUAL_WIDTH, texture_memory,
+};
 use macroquad::prelude::*;
-use std::{collections::HashMap, panic};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 占位角色剪影的尺寸（画布像素）
+const PLACEHOLDER_SIZE: Vec2 = vec2(360.0, 820.0);
+const PLACEHOLDER_COLOR: Color = Color::new(0.55, 0.6, 0.7, 0.6);
+
+/// 舞台上的站位，`X` 为相对屏幕宽度的比例 (0.0 - 1.0)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StageSlot {
+    Left,
+    CenterLeft,
+    Center,
+    CenterRight,
+    Right,
+    X(f32),
+}
+
+impl StageSlot {
+    /// 自动入场时依次尝试的站位
+    const AUTO_ORDER: [StageSlot; 5] = [
+        StageSlot::Center,
+        StageSlot::CenterLeft,
+        StageSlot::CenterRight,
+        StageSlot::Left,
+        StageSlot::Right,
+    ];
+
+    pub fn ratio(&self) -> f32 {
+        match self {
+            StageSlot::Left => 0.15,
+            StageSlot::CenterLeft => 0.325,
+            StageSlot::Center => 0.5,
+            StageSlot::CenterRight => 0.675,
+            StageSlot::Right => 0.85,
+            StageSlot::X(x) => *x,
+        }
+    }
+
+    pub fn x(&self, width: f32) -> f32 {
+        self.ratio() * width
+    }
+
+    /// 解析 `left` / `center-left` / `center` / `center-right` / `right` 或数值
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "left" => Some(StageSlot::Left),
+            "center-left" | "centerleft" => Some(StageSlot::CenterLeft),
+            "center" => Some(StageSlot::Center),
+            "center-right" | "centerright" => Some(StageSlot::CenterRight),
+            "right" => Some(StageSlot::Right),
+            other => other.parse::<f32>().ok().map(StageSlot::X),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StagePosition {
+    pub name: String,
+    pub slot: StageSlot,
+    /// 特写的缩放倍数
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+/// 突出说话角色的设置：其余在场角色乘以 `dim` 颜色变暗
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighlightSettings {
+    pub enabled: bool,
+    /// 变暗时乘上的颜色 (r, g, b)
+    pub dim: [f32; 3],
+    /// 明暗切换的秒数
+    pub duration: f32,
+    /// 旁白时是否让所有角色保持明亮
+    pub narration_lit: bool,
+}
+
+impl Default for HighlightSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dim: [0.5, 0.5, 0.55],
+            duration: 0.25,
+            narration_lit: true,
+        }
+    }
+}
+
+/// 说话时播放的嘴型动画，对应角色 Spine 中的嘴部轨道
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LipSync {
+    pub animation: String,
+    #[serde(default = "default_mouth_track")]
+    pub track: usize,
+    /// 停止说话时切回的动画，未设置时清空轨道
+    #[serde(default)]
+    pub idle: Option<String>,
+    #[serde(skip)]
+    talking: bool,
+}
+
+fn default_mouth_track() -> usize {
+    2
+}
+
+/// 眨眼设置，在独立轨道上按随机间隔播放一次眨眼动画
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Blink {
+    pub animation: String,
+    #[serde(default = "default_eye_track")]
+    pub track: usize,
+    /// 两次眨眼的间隔范围（秒）
+    #[serde(default = "default_blink_interval")]
+    pub interval: (f32, f32),
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(skip)]
+    timer: f32,
+}
+
+fn default_eye_track() -> usize {
+    3
+}
+
+fn default_blink_interval() -> (f32, f32) {
+    (2.0, 6.0)
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl Blink {
+    fn schedule(&mut self) {
+        let (min, max) = self.interval;
+        self.timer = if max > min {
+            rand::gen_range(min, max)
+        } else {
+            min
+        };
+    }
+}
 
 pub struct Character {
     pub name: String,
-    spine: Spine,
+    pub lip_sync: Option<LipSync>,
+    pub blink: Option<Blink>,
+    /// 相对站位脚下锚点的偏移（画布像素），用于对齐尺寸不同的骨骼
+    pub offset: Vec2,
+    /// 骨骼载入失败的角色为 `None`，以剪影占位
+    spine: Option<Spine>,
     texture: Texture2D,
     pub meshs: Vec<Mesh>,
 }
 
 impl Character {
-    pub fn update(&mut self, delta_time: f32, x: f32, y: f32) {
-        self.spine.controller.update(delta_time);
-        self.meshs = self.spine.get_mesh(self.texture.clone(), x, y);
+    /// 更新骨骼后再叠加舞台动作：位移、以脸部高度为中心的缩放和整体透明度
+    pub fn update_posed(&mut self, delta_time: f32, x: f32, y: f32, pose: Pose) {
+        if let Err(e) = self.update_blink(delta_time) {
+            println!("Warning: {}, blinking disabled.", e);
+            self.blink = None;
+        }
+        let x = x + self.offset.x;
+        let y = y + self.offset.y;
+        self.meshs = match self.spine.as_mut() {
+            Some(spine) => {
+                spine.controller.update(delta_time);
+                spine.get_mesh(self.texture.clone(), x + pose.offset.x, y + pose.offset.y)
+            }
+            None => vec![placeholder_mesh(x + pose.offset.x, y + pose.offset.y)],
+        };
+        if pose == Pose::default() {
+            return;
+        }
+        let anchor = vec2(x + pose.offset.x, y * 0.3 + pose.offset.y);
+        for mesh in &mut self.meshs {
+            for vertex in &mut mesh.vertices {
+                let position = anchor + (vertex.position.truncate() - anchor) * pose.scale;
+                vertex.position.x = position.x;
+                vertex.position.y = position.y;
+                let tint = [
+                    pose.tint.r,
+                    pose.tint.g,
+                    pose.tint.b,
+                    pose.alpha * pose.tint.a,
+                ];
+                for (channel, factor) in vertex.color.iter_mut().zip(tint) {
+                    *channel = (*channel as f32 * factor.clamp(0.0, 1.0)) as u8;
+                }
+            }
+        }
+    }
+    pub fn is_placeholder(&self) -> bool {
+        self.spine.is_none()
+    }
+
+    /// 纹理占用的字节数，占位角色为 0
+    pub fn memory(&self) -> usize {
+        match self.spine {
+            Some(_) => texture_memory(&self.texture),
+            None => 0,
+        }
+    }
+
+    fn spine_mut(&mut self) -> StoryResult<&mut Spine> {
+        self.spine
+            .as_mut()
+            .ok_or_else(|| StoryError::missing("skeleton", &self.name))
+    }
+
+    /// 把骨骼的错误标上角色名
+    fn animation_error(&self, source: SpineError) -> StoryError {
+        StoryError::Animation {
+            name: self.name.clone(),
+            source,
+        }
+    }
+
+    pub fn set_animation(&mut self, animation: &str) -> StoryResult<()> {
+        let result = self.spine_mut()?.set_animationn(animation, 0);
+        result.map_err(|e| self.animation_error(e))
+    }
+
+    pub fn set_track(
+        &mut self,
+        track: usize,
+        animation: &str,
+        looping: bool,
+        mix: Option<f32>,
+    ) -> StoryResult<()> {
+        let result = self
+            .spine_mut()?
+            .set_animation(track, animation, looping, mix);
+        result.map_err(|e| self.animation_error(e))
     }
-    pub fn set_animation(&mut self, animation: &str) {
-        self.spine.set_animationn(animation, 0).unwrap();
+
+    pub fn queue_track(
+        &mut self,
+        track: usize,
+        animation: &str,
+        looping: bool,
+        delay: f32,
+        mix: Option<f32>,
+    ) -> StoryResult<()> {
+        let result = self
+            .spine_mut()?
+            .add_animation(track, animation, looping, delay, mix);
+        result.map_err(|e| self.animation_error(e))
+    }
+
+    /// 开始或停止嘴型动画，状态未变化时不做任何事
+    pub fn set_talking(&mut self, talking: bool) -> StoryResult<()> {
+        let Some(lip_sync) = self.lip_sync.as_mut() else {
+            return Ok(());
+        };
+        if lip_sync.talking == talking {
+            return Ok(());
+        }
+        lip_sync.talking = talking;
+        let lip_sync = lip_sync.clone();
+        match (talking, &lip_sync.idle) {
+            (true, _) => self.set_track(lip_sync.track, &lip_sync.animation, true, Some(0.1)),
+            (false, Some(idle)) => self.set_track(lip_sync.track, idle, true, Some(0.1)),
+            (false, None) => {
+                self.clear_track(lip_sync.track, Some(0.1));
+                Ok(())
+            }
+        }
+    }
+
+    /// 计时到点时在眼睛轨道上播放一次眨眼，播完清空轨道，不影响其他轨道的动画
+    fn update_blink(&mut self, delta_time: f32) -> StoryResult<()> {
+        let Some(blink) = self.blink.as_mut().filter(|b| b.enabled) else {
+            return Ok(());
+        };
+        blink.timer -= delta_time;
+        if blink.timer > 0.0 {
+            return Ok(());
+        }
+        blink.schedule();
+        // 偶尔连眨两次
+        if rand::gen_range(0.0, 1.0) < 0.15 {
+            blink.timer = 0.3;
+        }
+        let (track, animation) = (blink.track, blink.animation.clone());
+        self.set_track(track, &animation, false, Some(0.0))?;
+        self.spine_mut()?.queue_clear_track(track, Some(0.05));
+        Ok(())
+    }
+
+    pub fn set_blinking(&mut self, enabled: bool) {
+        if let Some(blink) = self.blink.as_mut() {
+            blink.enabled = enabled;
+            blink.schedule();
+        }
+    }
+
+    pub fn clear_track(&mut self, track: usize, mix: Option<f32>) {
+        if let Some(spine) = self.spine.as_mut() {
+            spine.clear_track(track, mix);
+        }
+    }
+
+    pub fn set_scale(&mut self, scale: f32) {
+        if let Some(spine) = self.spine.as_mut() {
+            spine.scale = scale;
+        }
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        if let Some(spine) = self.spine.as_mut() {
+            spine.default_mix = mix;
+        }
+    }
+
+    pub fn set_skin(&mut self, skin: &str) -> StoryResult<()> {
+        let result = self.spine_mut()?.set_skin(skin);
+        result.map_err(|e| self.animation_error(e))
+    }
+
+    pub fn set_attachment(&mut self, slot: &str, attachment: Option<&str>) -> StoryResult<()> {
+        let result = self.spine_mut()?.set_attachment(slot, attachment);
+        result.map_err(|e| self.animation_error(e))
+    }
+}
+
+/// 占位角色的剪影，底边中点位于 (x, y)
+fn placeholder_mesh(x: f32, y: f32) -> Mesh {
+    let Vec2 { x: w, y: h } = PLACEHOLDER_SIZE;
+    let color = PLACEHOLDER_COLOR;
+    let corners = [
+        (x - w / 2.0, y - h),
+        (x + w / 2.0, y - h),
+        (x + w / 2.0, y),
+        (x - w / 2.0, y),
+    ];
+    Mesh {
+        vertices: corners
+            .iter()
+            .map(|&(x, y)| Vertex::new(x, y, 0.0, 0.0, 0.0, color))
+            .collect(),
+        indices: vec![0, 1, 2, 0, 2, 3],
+        texture: None,
     }
 }
 
 pub struct CharacterManager {
     pub characters: HashMap<String, Character>,
+    /// 在场角色，按入场顺序绘制
+    pub stage: Vec<StagePosition>,
+    /// 各角色正在播放的舞台动作
+    pub motions: HashMap<String, Vec<Motion>>,
+    pub highlight: HighlightSettings,
+    /// 当前说话的角色，`None` 为旁白
+    speaker: Option<String>,
+    /// 各角色的明亮程度，0.0 为完全变暗，1.0 为原色
+    lit: HashMap<String, f32>,
 }
 
 // CharacterManager 可以获取 TextureLoader 的实现
@@ -30,51 +375,285 @@ impl CharacterManager {
         name: String,
         texture: Texture2D,
         spinedemo: SpineDemo,
-    ) -> Result<(), String> {
-        let mut spine = Spine::load(spinedemo);
-        let meshs = spine.get_mesh(texture.clone(), screen_width() / 2.0, screen_height());
+    ) -> StoryResult<()> {
+        let mut spine = Spine::load(spinedemo).map_err(|source| StoryError::Character {
+            name: name.clone(),
+            source,
+        })?;
+        let meshs = spine.get_mesh(texture.clone(), VIRTUAL_WIDTH / 2.0, VIRTUAL_HEIGHT);
         self.characters.insert(
             name.clone(),
             Character {
                 name: name,
+                lip_sync: None,
+                blink: None,
+                offset: Vec2::ZERO,
                 texture: texture.clone(),
-                spine: spine,
+                spine: Some(spine),
                 meshs: meshs,
             },
         );
         Ok(())
     }
+
+    /// 注册一个没有骨骼的占位角色，用于资源缺失时让剧情照常进行
+    pub fn register_placeholder(&mut self, name: String) {
+        self.characters.insert(
+            name.clone(),
+            Character {
+                name: name,
+                lip_sync: None,
+                blink: None,
+                offset: Vec2::ZERO,
+                texture: Texture2D::empty(),
+                spine: None,
+                meshs: Vec::new(),
+            },
+        );
+    }
 }
 
 impl CharacterManager {
     pub fn new() -> Self {
         Self {
             characters: HashMap::new(),
+            stage: Vec::new(),
+            motions: HashMap::new(),
+            highlight: HighlightSettings::default(),
+            speaker: None,
+            lit: HashMap::new(),
         }
     }
-    pub fn update(&mut self, name: &str, delta_time: f32, x: f32, y: f32) -> Result<(), String> {
-        if let Some(character) = self.characters.get_mut(name) {
-            character.update(delta_time, x, y);
-            Ok(())
+
+    /// 释放不在场的角色，在场时返回 `false`
+    pub fn unload(&mut self, name: &str) -> bool {
+        if self.is_on_stage(name) {
+            return false;
+        }
+        self.motions.remove(name);
+        self.lit.remove(name);
+        self.characters.remove(name).is_some()
+    }
+
+    pub fn character_mut(&mut self, name: &str) -> StoryResult<&mut Character> {
+        self.characters
+            .get_mut(name)
+            .ok_or_else(|| StoryError::missing("character", name))
+    }
+
+    /// 只有 `speaker` 在说话时动嘴，其余角色闭嘴；出错的角色会关闭口型同步
+    pub fn update_lip_sync(&mut self, speaker: Option<&str>, talking: bool) {
+        for character in self.characters.values_mut() {
+            let talking = talking && Some(character.name.as_str()) == speaker;
+            if let Err(e) = character.set_talking(talking) {
+                println!("Warning: {}, lip sync disabled.", e);
+                character.lip_sync = None;
+            }
+        }
+    }
+
+    pub fn set_animation(&mut self, name: &str, animation: &str) -> StoryResult<()> {
+        self.character_mut(name)?.set_animation(animation)
+    }
+}
+
+// 舞台管理：角色入场后一直保留，直到被显式移除
+impl CharacterManager {
+    pub fn show(&mut self, name: &str, slot: StageSlot) {
+        // 正在退场的角色重新入场时取消退场动作
+        if let Some(motions) = self.motions.get_mut(name) {
+            motions.retain(|m| !m.exiting);
+        }
+        if let Some(position) = self.stage.iter_mut().find(|p| p.name == name) {
+            position.slot = slot;
         } else {
-            Err(format!("Character {} not found", name))
+            self.stage.push(StagePosition {
+                name: name.to_string(),
+                slot,
+                scale: 1.0,
+            });
         }
     }
 
-    pub fn get_meshs(&self, name: &str) -> &Vec<Mesh> {
-        &self
-            .characters
-            .get(name)
-            .unwrap_or_else(|| panic!("can not found character {}.", name))
-            .meshs
+    /// 入场并播放入场动作
+    pub fn enter(&mut self, name: &str, slot: StageSlot, spec: MotionSpec) {
+        self.show(name, slot);
+        self.play_motion(name, spec);
     }
-    pub fn get_decrible(&self, name: &str) -> String {
-        self.characters.get(name).unwrap().name.clone()
+
+    /// 平滑移动到新的站位，角色不在场时直接入场
+    pub fn move_to(&mut self, name: &str, slot: StageSlot, duration: f32, easing: Easing) {
+        let from = self
+            .stage
+            .iter()
+            .find(|p| p.name == name)
+            .map(|p| p.slot.ratio());
+        self.show(name, slot);
+        if let Some(from) = from.filter(|from| *from != slot.ratio()) {
+            self.play_motion(
+                name,
+                MotionSpec {
+                    kind: MotionKind::Move { from },
+                    duration,
+                    easing,
+                },
+            );
+        }
     }
-    pub fn set_animation(&mut self, name: &str, animation: &str) {
-        self.characters
-            .get_mut(name)
-            .unwrap()
-            .set_animation(animation);
+
+    /// 播放退场动作，结束后离场
+    pub fn exit(&mut self, name: &str, spec: MotionSpec) {
+        if !self.is_on_stage(name) {
+            return;
+        }
+        self.motions
+            .entry(name.to_string())
+            .or_default()
+            .push(Motion::new(spec, true));
+    }
+
+    /// 播放强调动作；特写会记录到站位上，一直保持到下一次特写
+    pub fn play_motion(&mut self, name: &str, spec: MotionSpec) {
+        let mut spec = spec;
+        if let MotionKind::CloseUp { scale, .. } = spec.kind {
+            let Some(position) = self.stage.iter_mut().find(|p| p.name == name) else {
+                return;
+            };
+            spec.kind = MotionKind::CloseUp {
+                from: position.scale,
+                scale,
+            };
+            position.scale = scale;
+        }
+        let motions = self.motions.entry(name.to_string()).or_default();
+        // 同类动作只保留最新的一个
+        motions
+            .retain(|m| std::mem::discriminant(&m.spec.kind) != std::mem::discriminant(&spec.kind));
+        motions.push(Motion::new(spec, false));
+    }
+
+    /// 角色未在场时放到第一个空闲的站位
+    pub fn show_auto(&mut self, name: &str) {
+        if self.is_on_stage(name) || !self.characters.contains_key(name) {
+            return;
+        }
+        let slot = self.free_slot();
+        self.show(name, slot);
+    }
+
+    /// 设置当前说话的角色，其余角色按 [`HighlightSettings`] 逐渐变暗
+    pub fn set_speaker(&mut self, speaker: Option<&str>) {
+        self.speaker = speaker.map(str::to_string);
+    }
+
+    fn is_lit(&self, name: &str) -> bool {
+        if !self.highlight.enabled {
+            return true;
+        }
+        match &self.speaker {
+            Some(speaker) => speaker == name || !self.is_on_stage(speaker),
+            None => self.highlight.narration_lit,
+        }
+    }
+
+    /// 第一个无人占用的站位，全满时返回中间
+    pub fn free_slot(&self) -> StageSlot {
+        StageSlot::AUTO_ORDER
+            .iter()
+            .find(|slot| !self.stage.iter().any(|p| p.slot == **slot))
+            .copied()
+            .unwrap_or(StageSlot::Center)
+    }
+
+    pub fn hide(&mut self, name: &str) {
+        self.stage.retain(|p| p.name != name);
+        self.motions.remove(name);
+        self.lit.remove(name);
+    }
+
+    pub fn clear_stage(&mut self) {
+        self.stage.clear();
+        self.motions.clear();
+        self.lit.clear();
+    }
+
+    pub fn is_on_stage(&self, name: &str) -> bool {
+        self.stage.iter().any(|p| p.name == name)
+    }
+
+    pub fn update_stage(&mut self, delta_time: f32, width: f32, height: f32) -> StoryResult<()> {
+        let mut exited = Vec::new();
+        let mut missing = Vec::new();
+        let step = if self.highlight.duration > 0.0 {
+            delta_time / self.highlight.duration
+        } else {
+            1.0
+        };
+        for position in &self.stage {
+            let target = if self.is_lit(&position.name) {
+                1.0
+            } else {
+                0.0
+            };
+            // 新入场的角色直接使用目标亮度
+            let lit = self.lit.entry(position.name.clone()).or_insert(target);
+            *lit = if *lit < target {
+                (*lit + step).min(target)
+            } else {
+                (*lit - step).max(target)
+            };
+            let [r, g, b] = self.highlight.dim;
+            let mix = |dim: f32| dim + (1.0 - dim) * *lit;
+            let mut pose = Pose {
+                scale: position.scale,
+                tint: Color::new(mix(r), mix(g), mix(b), 1.0),
+                ..Pose::default()
+            };
+            if let Some(motions) = self.motions.get_mut(&position.name) {
+                for motion in motions.iter_mut() {
+                    motion.update(delta_time);
+                    pose = pose.combine(motion.pose(position.slot.ratio(), width, height));
+                }
+                if motions.iter().any(|m| m.exiting && m.is_finished()) {
+                    exited.push(position.name.clone());
+                }
+                motions.retain(|m| !m.is_finished());
+            }
+            if let Some(character) = self.characters.get_mut(&position.name) {
+                character.update_posed(delta_time, position.slot.x(width), height, pose);
+            } else {
+                missing.push(position.name.clone());
+            }
+        }
+        for name in exited {
+            self.hide(&name);
+        }
+        // 未载入的角色移出舞台，其余角色照常更新
+        for name in &missing {
+            self.hide(name);
+        }
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(StoryError::missing("character", &missing.join(", ")))
+        }
+    }
+
+    /// 按绘制顺序返回在场角色的网格，`front` 指定的角色最后绘制
+    pub fn stage_meshs(&self, front: Option<&str>) -> Vec<&Vec<Mesh>> {
+        let mut names: Vec<&str> = self
+            .stage
+            .iter()
+            .map(|p| p.name.as_str())
+            .filter(|name| Some(*name) != front)
+            .collect();
+        if let Some(front) = front.filter(|front| self.is_on_stage(front)) {
+            names.push(front);
+        }
+        names
+            .into_iter()
+            .filter_map(|name| self.characters.get(name))
+            .map(|character| &character.meshs)
+            .collect()
     }
 }
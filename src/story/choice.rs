@@ -0,0 +1,134 @@
+use macroquad::math::{Rect, Vec2};
+
+/// 选项按钮的交互状态；布局每帧按当前窗口大小重新计算，绘制和点击检测共用同一份布局
+#[derive(Debug, Clone)]
+pub struct ChoiceMenu {
+    /// 鼠标悬停或方向键选中的选项
+    pub focused: Option<usize>,
+    /// 鼠标按下但尚未松开的选项
+    pub pressed: Option<usize>,
+    /// 选项出现后经过的秒数
+    pub elapsed: f32,
+    /// 每个按钮的出场动画时长
+    pub appear_duration: f32,
+    /// 相邻按钮出场的间隔
+    pub appear_stagger: f32,
+}
+
+impl ChoiceMenu {
+    pub fn new() -> Self {
+        Self {
+            focused: None,
+            pressed: None,
+            elapsed: 0.0,
+            appear_duration: 0.25,
+            appear_stagger: 0.06,
+        }
+    }
+
+    /// 新的一组选项出现时调用
+    pub fn reset(&mut self) {
+        self.focused = None;
+        self.pressed = None;
+        self.elapsed = 0.0;
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.elapsed += delta_time;
+    }
+
+    /// 第 `index` 个按钮的出场进度 (0.0 - 1.0)，已做缓出
+    pub fn appear_progress(&self, index: usize) -> f32 {
+        let t = ((self.elapsed - index as f32 * self.appear_stagger) / self.appear_duration)
+            .clamp(0.0, 1.0);
+        1.0 - (1.0 - t) * (1.0 - t)
+    }
+
+    /// 全部按钮出场完毕后才接受输入，避免推进台词的那次点击直接选中选项
+    pub fn is_ready(&self, count: usize) -> bool {
+        count == 0 || self.appear_progress(count - 1) >= 1.0
+    }
+
+    /// 按窗口大小计算每个按钮的位置，整体在屏幕上方偏中居中
+    pub fn layout(count: usize, screen_width: f32, screen_height: f32) -> Vec<Rect> {
+        let width = (screen_width * 0.6).max(320.0);
+        let height = (screen_height * 0.08).max(48.0);
+        let spacing = height * 0.35;
+        let total = count as f32 * height + count.saturating_sub(1) as f32 * spacing;
+        let top = screen_height * 0.42 - total / 2.0;
+        let x = (screen_width - width) / 2.0;
+        (0..count)
+            .map(|i| Rect::new(x, top + i as f32 * (height + spacing), width, height))
+            .collect()
+    }
+
+    pub fn hit_test(rects: &[Rect], point: Vec2) -> Option<usize> {
+        rects.iter().position(|rect| rect.contains(point))
+    }
+
+    /// 方向键移动焦点，`delta` 为 -1 或 1，首尾循环
+    pub fn move_focus(&mut self, delta: i32, count: usize) {
+        if count == 0 {
+            return;
+        }
+        let count = count as i32;
+        self.focused = Some(match self.focused {
+            Some(current) => (current as i32 + delta).rem_euclid(count) as usize,
+            None if delta > 0 => 0,
+            None => (count - 1) as usize,
+        });
+    }
+}
+
+impl Default for ChoiceMenu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::vec2;
+
+    const SIZES: [(f32, f32); 2] = [(1920.0, 1080.0), (800.0, 600.0)];
+
+    #[test]
+    fn centers_hit_their_own_box() {
+        for (width, height) in SIZES {
+            for count in [1, 3, 5] {
+                let rects = ChoiceMenu::layout(count, width, height);
+                assert_eq!(rects.len(), count);
+                for (i, rect) in rects.iter().enumerate() {
+                    assert_eq!(ChoiceMenu::hit_test(&rects, rect.center()), Some(i));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gaps_and_outside_miss() {
+        for (width, height) in SIZES {
+            for count in [1, 3, 5] {
+                let rects = ChoiceMenu::layout(count, width, height);
+                let first = rects[0];
+                let last = rects[count - 1];
+                let center_x = first.center().x;
+                for pair in rects.windows(2) {
+                    let gap = (pair[0].bottom() + pair[1].top()) / 2.0;
+                    assert!(pair[0].bottom() < pair[1].top());
+                    assert_eq!(ChoiceMenu::hit_test(&rects, vec2(center_x, gap)), None);
+                }
+                let outside = [
+                    vec2(center_x, first.top() - 1.0),
+                    vec2(center_x, last.bottom() + 1.0),
+                    vec2(first.left() - 1.0, first.center().y),
+                    vec2(first.right() + 1.0, first.center().y),
+                ];
+                for point in outside {
+                    assert_eq!(ChoiceMenu::hit_test(&rects, point), None);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,928 @@
+use std::collections::HashMap;
+
+use crate::story::character::StageSlot;
+use crate::story::{
+    BackgroundFit, Easing, Framing, KenBurns, MotionKind, MotionSpec, TransitionKind,
+    TransitionSpec,
+};
+
+/// 由 ink 标签解析出的演出指令
+/// 语法：`# 指令: 对象 参数=值 ...`，例如 `# show: 阿罗娜 at=left anim=03`
+#[derive(Debug, Clone, PartialEq)]
+pub enum StageCommand {
+    /// `transition` 为 `None` 时立即切换；`fit` 与 `ken_burns` 覆盖清单中的设置
+    Background {
+        name: String,
+        transition: Option<TransitionSpec>,
+        fit: Option<BackgroundFit>,
+        ken_burns: Option<KenBurns>,
+    },
+    /// 不切换背景的单独转场，例如闪白
+    Transition {
+        spec: TransitionSpec,
+    },
+    /// `name` 为 `None` 时停止当前 BGM
+    Bgm {
+        name: Option<String>,
+        fade: f32,
+    },
+    Sfx {
+        name: String,
+    },
+    /// 为所在台词指定语音，代替按命名约定查找的语音
+    Voice {
+        name: String,
+    },
+    /// 循环环境音，`name` 为 `None` 时停止
+    Ambience {
+        name: Option<String>,
+        fade: f32,
+    },
+    /// 角色已在场且给出 `slot` 时平滑移动过去，`duration`/`easing` 同时作用于入场与移动
+    Show {
+        name: String,
+        slot: Option<StageSlot>,
+        animation: Option<String>,
+        enter: Option<MotionKind>,
+        duration: Option<f32>,
+        easing: Option<Easing>,
+    },
+    /// `exit` 为 `None` 时立即离场
+    Hide {
+        name: String,
+        exit: Option<MotionSpec>,
+    },
+    /// 强调动作：跳跃、抖动、特写，`name` 为 `None` 时作用于当前说话的角色
+    Motion {
+        name: Option<String>,
+        spec: MotionSpec,
+    },
+    /// 开关说话角色高亮，`narration_lit` 设置旁白时是否全部保持明亮
+    Highlight {
+        enabled: bool,
+        narration_lit: Option<bool>,
+    },
+    /// `name` 为 `None` 时作用于当前说话的角色
+    Animation {
+        name: Option<String>,
+        animation: String,
+    },
+    /// 指定轨道的 Spine 动画，表情、眼睛、嘴型通常各占一条轨道；
+    /// `animation` 为 `None` 时清空轨道，`queue` 为排队播放的延迟
+    Track {
+        name: Option<String>,
+        animation: Option<String>,
+        track: usize,
+        looping: bool,
+        mix: Option<f32>,
+        queue: Option<f32>,
+    },
+    /// 角色切换动画时默认的混合秒数
+    Mix {
+        name: Option<String>,
+        duration: f32,
+    },
+    Skin {
+        name: Option<String>,
+        skin: String,
+    },
+    /// 开关自动眨眼
+    Blink {
+        name: Option<String>,
+        enabled: bool,
+    },
+    /// `attachment` 为 `None` 时隐藏插槽
+    Attachment {
+        name: Option<String>,
+        slot: String,
+        attachment: Option<String>,
+    },
+}
+
+struct TagArgs<'a> {
+    key: &'a str,
+    positional: Vec<&'a str>,
+    options: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> TagArgs<'a> {
+    fn parse(key: &'a str, value: &'a str) -> Self {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        for token in value.split_whitespace() {
+            if let Some((k, v)) = token.split_once('=') {
+                options.insert(k, v);
+            } else {
+                positional.push(token);
+            }
+        }
+        Self {
+            key,
+            positional,
+            options,
+        }
+    }
+
+    fn subject(&self) -> Result<String, String> {
+        self.positional
+            .first()
+            .map(|s| s.to_string())
+            .ok_or_else(|| format!("`{}` requires a target", self.key))
+    }
+
+    fn check(&self, max_positional: usize, allowed: &[&str]) -> Result<(), String> {
+        if self.positional.len() > max_positional {
+            return Err(format!(
+                "`{}` got unexpected argument `{}`",
+                self.key, self.positional[max_positional]
+            ));
+        }
+        if let Some(option) = self.options.keys().find(|k| !allowed.contains(k)) {
+            return Err(format!("`{}` got unknown option `{}`", self.key, option));
+        }
+        Ok(())
+    }
+
+    fn float(&self, option: &str, default: f32) -> Result<f32, String> {
+        Ok(self.optional_float(option)?.unwrap_or(default))
+    }
+
+    fn optional_float(&self, option: &str) -> Result<Option<f32>, String> {
+        match self.options.get(option) {
+            Some(value) => value
+                .parse::<f32>()
+                .map(Some)
+                .map_err(|_| format!("`{}` option `{}` is not a number", self.key, option)),
+            None => Ok(None),
+        }
+    }
+
+    /// 参数数为 `count` 时作用于说话角色，多一个时第一个为角色名
+    fn targeted(&self, count: usize) -> Result<(Option<String>, Vec<&'a str>), String> {
+        if self.positional.len() == count {
+            Ok((None, self.positional.clone()))
+        } else if self.positional.len() == count + 1 {
+            Ok((
+                Some(self.positional[0].to_string()),
+                self.positional[1..].to_vec(),
+            ))
+        } else {
+            Err(format!(
+                "`{}` expects {} argument(s) after an optional character name",
+                self.key, count
+            ))
+        }
+    }
+
+    fn boolean(&self, option: &str, default: bool) -> Result<bool, String> {
+        match self.options.get(option) {
+            Some(&"true") => Ok(true),
+            Some(&"false") => Ok(false),
+            Some(value) => Err(format!(
+                "`{}` option `{}` expects true or false, got `{}`",
+                self.key, option, value
+            )),
+            None => Ok(default),
+        }
+    }
+
+    fn easing(&self) -> Result<Option<Easing>, String> {
+        match self.options.get("ease") {
+            Some(ease) => Easing::parse(ease)
+                .map(Some)
+                .ok_or_else(|| format!("unknown easing `{}`", ease)),
+            None => Ok(None),
+        }
+    }
+
+    /// 以 `kind` 的默认值为基础，套用 `time=<秒>` 与 `ease=<曲线>`
+    fn motion(&self, kind: MotionKind) -> Result<MotionSpec, String> {
+        let mut spec = MotionSpec::new(kind);
+        spec.duration = self.float("time", spec.duration)?;
+        if let Some(easing) = self.easing()? {
+            spec.easing = easing;
+        }
+        Ok(spec)
+    }
+
+    /// `zoom=1.0>1.2`、`pan=0,0>0.05,0` 与可选的 `dur=<秒>`，只给一个值时镜头保持不动
+    fn ken_burns(&self) -> Result<Option<KenBurns>, String> {
+        let zoom = self.options.get("zoom");
+        let pan = self.options.get("pan");
+        if zoom.is_none() && pan.is_none() {
+            if self.options.contains_key("dur") {
+                return Err(format!(
+                    "`{}` option `dur` requires `zoom` or `pan`",
+                    self.key
+                ));
+            }
+            return Ok(None);
+        }
+        let number = |value: &str| {
+            value
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| format!("`{}` got malformed camera value `{}`", self.key, value))
+        };
+        let range = |value: &'a str| value.split_once('>').unwrap_or((value, value));
+        let mut from = Framing::default();
+        let mut to = Framing::default();
+        if let Some(zoom) = zoom {
+            let (a, b) = range(zoom);
+            from.zoom = number(a)?;
+            to.zoom = number(b)?;
+        }
+        if let Some(pan) = pan {
+            let (a, b) = range(pan);
+            let point = |value: &str| match value.split_once(',') {
+                Some((x, y)) => Ok((number(x)?, number(y)?)),
+                None => Err(format!("`{}` option `pan` expects `x,y`", self.key)),
+            };
+            from.pan = point(a)?;
+            to.pan = point(b)?;
+        }
+        Ok(Some(KenBurns {
+            from,
+            to,
+            duration: self.float("dur", 10.0)?,
+        }))
+    }
+
+    /// `with=<种类>` 与可选的 `time=<秒>`
+    fn transition(&self) -> Result<Option<TransitionSpec>, String> {
+        let Some(with) = self.options.get("with") else {
+            if self.options.contains_key("time") {
+                return Err(format!("`{}` option `time` requires `with`", self.key));
+            }
+            return Ok(None);
+        };
+        let kind =
+            TransitionKind::parse(with).ok_or_else(|| format!("unknown transition `{}`", with))?;
+        Ok(Some(TransitionSpec {
+            kind,
+            duration: self.float("time", kind.default_duration())?,
+        }))
+    }
+}
+
+impl StageCommand {
+    /// 解析单个标签；不含冒号的标签视为当前说话角色的动画名
+    pub fn parse(tag: &str) -> Result<Self, String> {
+        let tag = tag.trim();
+        let Some((key, value)) = tag.split_once([':', '：']) else {
+            if tag.is_empty() || tag.contains(char::is_whitespace) {
+                return Err(format!("malformed tag `{}`", tag));
+            }
+            return Ok(StageCommand::Animation {
+                name: None,
+                animation: tag.to_string(),
+            });
+        };
+        let args = TagArgs::parse(key.trim(), value.trim());
+        match args.key {
+            "bg" => {
+                args.check(1, &["with", "time", "fit", "zoom", "pan", "dur"])?;
+                let fit = match args.options.get("fit") {
+                    Some(fit) => Some(
+                        BackgroundFit::parse(fit)
+                            .ok_or_else(|| format!("unknown fit `{}`", fit))?,
+                    ),
+                    None => None,
+                };
+                Ok(StageCommand::Background {
+                    name: args.subject()?,
+                    transition: args.transition()?,
+                    fit,
+                    ken_burns: args.ken_burns()?,
+                })
+            }
+            "transition" => {
+                args.check(1, &["time"])?;
+                let kind = args.subject()?;
+                let kind = TransitionKind::parse(&kind)
+                    .ok_or_else(|| format!("unknown transition `{}`", kind))?;
+                Ok(StageCommand::Transition {
+                    spec: TransitionSpec {
+                        kind,
+                        duration: args.float("time", kind.default_duration())?,
+                    },
+                })
+            }
+            "bgm" => {
+                args.check(1, &["fade"])?;
+                let name = args.subject()?;
+                Ok(StageCommand::Bgm {
+                    name: if name == "stop" { None } else { Some(name) },
+                    fade: args.float("fade", 0.0)?,
+                })
+            }
+            "sfx" => {
+                args.check(1, &[])?;
+                Ok(StageCommand::Sfx {
+                    name: args.subject()?,
+                })
+            }
+            "voice" => {
+                args.check(1, &[])?;
+                Ok(StageCommand::Voice {
+                    name: args.subject()?,
+                })
+            }
+            "amb" => {
+                args.check(1, &["fade"])?;
+                let name = args.subject()?;
+                Ok(StageCommand::Ambience {
+                    name: if name == "stop" { None } else { Some(name) },
+                    fade: args.float("fade", 0.0)?,
+                })
+            }
+            "show" => {
+                args.check(1, &["at", "anim", "enter", "time", "ease"])?;
+                let slot = match args.options.get("at") {
+                    Some(at) => {
+                        Some(StageSlot::parse(at).ok_or_else(|| format!("unknown slot `{}`", at))?)
+                    }
+                    None => None,
+                };
+                let enter = match args.options.get("enter") {
+                    Some(enter) => Some(
+                        MotionKind::parse_transition(enter)
+                            .ok_or_else(|| format!("unknown enter motion `{}`", enter))?,
+                    ),
+                    None => None,
+                };
+                Ok(StageCommand::Show {
+                    name: args.subject()?,
+                    slot,
+                    animation: args.options.get("anim").map(|s| s.to_string()),
+                    enter,
+                    duration: args.optional_float("time")?,
+                    easing: args.easing()?,
+                })
+            }
+            "hide" => {
+                args.check(1, &["exit", "time", "ease"])?;
+                let exit = match args.options.get("exit") {
+                    Some(exit) => Some(
+                        args.motion(
+                            MotionKind::parse_transition(exit)
+                                .ok_or_else(|| format!("unknown exit motion `{}`", exit))?,
+                        )?,
+                    ),
+                    None => None,
+                };
+                Ok(StageCommand::Hide {
+                    name: args.subject()?,
+                    exit,
+                })
+            }
+            "dim" => {
+                args.check(1, &["narration"])?;
+                let enabled = match args.subject()?.as_str() {
+                    "on" => true,
+                    "off" => false,
+                    other => return Err(format!("`dim` expects `on` or `off`, got `{}`", other)),
+                };
+                let narration_lit = match args.options.get("narration") {
+                    Some(&"lit") => Some(true),
+                    Some(&"dim") => Some(false),
+                    Some(other) => {
+                        return Err(format!("`dim` option `narration` got `{}`", other));
+                    }
+                    None => None,
+                };
+                Ok(StageCommand::Highlight {
+                    enabled,
+                    narration_lit,
+                })
+            }
+            "face" => {
+                args.check(2, &["track", "loop", "mix", "queue"])?;
+                let (name, rest) = args.targeted(1)?;
+                let track = match args.options.get("track") {
+                    Some(track) => track
+                        .parse::<usize>()
+                        .map_err(|_| format!("`face` option `track` got `{}`", track))?,
+                    None => 1,
+                };
+                Ok(StageCommand::Track {
+                    name,
+                    animation: (rest[0] != "none").then(|| rest[0].to_string()),
+                    track,
+                    looping: args.boolean("loop", true)?,
+                    mix: args.optional_float("mix")?,
+                    queue: args.optional_float("queue")?,
+                })
+            }
+            "mix" => {
+                args.check(2, &[])?;
+                let (name, rest) = args.targeted(1)?;
+                let duration = rest[0]
+                    .parse::<f32>()
+                    .map_err(|_| format!("`mix` expects seconds, got `{}`", rest[0]))?;
+                Ok(StageCommand::Mix { name, duration })
+            }
+            "blink" => {
+                args.check(2, &[])?;
+                let (name, rest) = args.targeted(1)?;
+                let enabled = match rest[0] {
+                    "on" => true,
+                    "off" => false,
+                    other => {
+                        return Err(format!("`blink` expects `on` or `off`, got `{}`", other));
+                    }
+                };
+                Ok(StageCommand::Blink { name, enabled })
+            }
+            "skin" => {
+                args.check(2, &[])?;
+                let (name, rest) = args.targeted(1)?;
+                Ok(StageCommand::Skin {
+                    name,
+                    skin: rest[0].to_string(),
+                })
+            }
+            "attach" => {
+                args.check(3, &[])?;
+                let (name, rest) = args.targeted(2)?;
+                Ok(StageCommand::Attachment {
+                    name,
+                    slot: rest[0].to_string(),
+                    attachment: (rest[1] != "none").then(|| rest[1].to_string()),
+                })
+            }
+            "act" => {
+                args.check(2, &["scale", "time", "ease"])?;
+                let (name, action) = match args.positional.as_slice() {
+                    [action] => (None, *action),
+                    [name, action] => (Some(name.to_string()), *action),
+                    _ => return Err("`act` requires an action".to_string()),
+                };
+                let kind = match action {
+                    "hop" => MotionKind::Hop,
+                    "shake" => MotionKind::Shake,
+                    "closeup" => MotionKind::CloseUp {
+                        from: 1.0,
+                        scale: args.float("scale", 1.3)?,
+                    },
+                    other => return Err(format!("unknown action `{}`", other)),
+                };
+                Ok(StageCommand::Motion {
+                    name,
+                    spec: args.motion(kind)?,
+                })
+            }
+            "anim" => {
+                args.check(2, &[])?;
+                match args.positional.as_slice() {
+                    [animation] => Ok(StageCommand::Animation {
+                        name: None,
+                        animation: animation.to_string(),
+                    }),
+                    [name, animation] => Ok(StageCommand::Animation {
+                        name: Some(name.to_string()),
+                        animation: animation.to_string(),
+                    }),
+                    _ => Err("`anim` requires an animation name".to_string()),
+                }
+            }
+            other => Err(format!("unknown tag command `{}`", other)),
+        }
+    }
+
+    /// 解析一行的全部标签，无法识别的标签连同所在台词一起打印警告后跳过
+    pub fn parse_tags(line: &inkling::Line) -> Vec<Self> {
+        let mut commands = Vec::new();
+        for (index, tag) in line.tags.iter().enumerate() {
+            let parsed = match Self::parse(tag) {
+                Ok(StageCommand::Animation { name: None, .. })
+                    if index > 0 && !tag.contains([':', '：']) =>
+                {
+                    Err(format!("bare tag `{}` is only allowed first", tag.trim()))
+                }
+                parsed => parsed,
+            };
+            match parsed {
+                Ok(command) => commands.push(command),
+                Err(e) => println!(
+                    "Warning: {} in tag `# {}` of line \"{}\".",
+                    e,
+                    tag,
+                    line.text.trim_end()
+                ),
+            }
+        }
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(tag: &str) -> StageCommand {
+        StageCommand::parse(tag).unwrap()
+    }
+
+    fn error(tag: &str) -> String {
+        StageCommand::parse(tag).unwrap_err()
+    }
+
+    fn tags(tags: &[&str]) -> Vec<StageCommand> {
+        StageCommand::parse_tags(&inkling::Line {
+            text: "阿罗娜: 嗯。\n".to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn bare_tag_is_speaker_animation() {
+        assert_eq!(
+            parse(" 03 "),
+            StageCommand::Animation {
+                name: None,
+                animation: "03".to_string()
+            }
+        );
+        assert_eq!(error(""), "malformed tag ``");
+        assert_eq!(error("two words"), "malformed tag `two words`");
+    }
+
+    #[test]
+    fn full_width_colon() {
+        assert_eq!(
+            parse("sfx：door"),
+            StageCommand::Sfx {
+                name: "door".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn background() {
+        assert_eq!(
+            parse("bg: BG_Classroom"),
+            StageCommand::Background {
+                name: "BG_Classroom".to_string(),
+                transition: None,
+                fit: None,
+                ken_burns: None,
+            }
+        );
+        assert_eq!(
+            parse("bg: BG_Classroom with=dissolve time=1.2 fit=contain"),
+            StageCommand::Background {
+                name: "BG_Classroom".to_string(),
+                transition: Some(TransitionSpec {
+                    kind: TransitionKind::Dissolve,
+                    duration: 1.2,
+                }),
+                fit: Some(BackgroundFit::Contain),
+                ken_burns: None,
+            }
+        );
+        assert_eq!(error("bg:"), "`bg` requires a target");
+        assert_eq!(error("bg: A B"), "`bg` got unexpected argument `B`");
+        assert_eq!(error("bg: A time=1"), "`bg` option `time` requires `with`");
+        assert_eq!(error("bg: A with=spin"), "unknown transition `spin`");
+        assert_eq!(error("bg: A fit=tile"), "unknown fit `tile`");
+        assert_eq!(error("bg: A size=2"), "`bg` got unknown option `size`");
+    }
+
+    #[test]
+    fn background_ken_burns() {
+        let StageCommand::Background { ken_burns, .. } =
+            parse("bg: A zoom=1.0>1.2 pan=0,0>0.05,-0.1 dur=8")
+        else {
+            panic!("expected a background");
+        };
+        assert_eq!(
+            ken_burns,
+            Some(KenBurns {
+                from: Framing {
+                    zoom: 1.0,
+                    pan: (0.0, 0.0),
+                },
+                to: Framing {
+                    zoom: 1.2,
+                    pan: (0.05, -0.1),
+                },
+                duration: 8.0,
+            })
+        );
+        // 只给一个值时镜头保持不动
+        let StageCommand::Background { ken_burns, .. } = parse("bg: A zoom=1.1") else {
+            panic!("expected a background");
+        };
+        let ken_burns = ken_burns.unwrap();
+        assert_eq!(ken_burns.from.zoom, 1.1);
+        assert_eq!(ken_burns.to.zoom, 1.1);
+        assert_eq!(ken_burns.duration, 10.0);
+
+        assert_eq!(
+            error("bg: A dur=3"),
+            "`bg` option `dur` requires `zoom` or `pan`"
+        );
+        assert_eq!(
+            error("bg: A zoom=big"),
+            "`bg` got malformed camera value `big`"
+        );
+        assert_eq!(error("bg: A pan=0.1"), "`bg` option `pan` expects `x,y`");
+    }
+
+    #[test]
+    fn transition() {
+        assert_eq!(
+            parse("transition: flash"),
+            StageCommand::Transition {
+                spec: TransitionSpec {
+                    kind: TransitionKind::Flash,
+                    duration: 0.5,
+                }
+            }
+        );
+        assert_eq!(error("transition: spin"), "unknown transition `spin`");
+        assert_eq!(
+            error("transition: fade with=wipe"),
+            "`transition` got unknown option `with`"
+        );
+    }
+
+    #[test]
+    fn music_and_sounds() {
+        assert_eq!(
+            parse("bgm: calm fade=1.5"),
+            StageCommand::Bgm {
+                name: Some("calm".to_string()),
+                fade: 1.5
+            }
+        );
+        assert_eq!(
+            parse("bgm: stop"),
+            StageCommand::Bgm {
+                name: None,
+                fade: 0.0
+            }
+        );
+        assert_eq!(
+            parse("amb: rain"),
+            StageCommand::Ambience {
+                name: Some("rain".to_string()),
+                fade: 0.0
+            }
+        );
+        assert_eq!(
+            parse("amb: stop fade=2"),
+            StageCommand::Ambience {
+                name: None,
+                fade: 2.0
+            }
+        );
+        assert_eq!(
+            parse("voice: 阿罗娜/special_01"),
+            StageCommand::Voice {
+                name: "阿罗娜/special_01".to_string()
+            }
+        );
+        assert_eq!(
+            error("bgm: calm fade=slow"),
+            "`bgm` option `fade` is not a number"
+        );
+        assert_eq!(
+            error("sfx: door volume=1"),
+            "`sfx` got unknown option `volume`"
+        );
+    }
+
+    #[test]
+    fn show_and_hide() {
+        assert_eq!(
+            parse("show: 阿罗娜 at=left anim=03 enter=slide-left time=0.6 ease=back"),
+            StageCommand::Show {
+                name: "阿罗娜".to_string(),
+                slot: Some(StageSlot::Left),
+                animation: Some("03".to_string()),
+                enter: Some(MotionKind::SlideLeft),
+                duration: Some(0.6),
+                easing: Some(Easing::Back),
+            }
+        );
+        assert_eq!(
+            parse("show: 阿罗娜 at=0.3"),
+            StageCommand::Show {
+                name: "阿罗娜".to_string(),
+                slot: Some(StageSlot::X(0.3)),
+                animation: None,
+                enter: None,
+                duration: None,
+                easing: None,
+            }
+        );
+        assert_eq!(
+            parse("hide: 阿罗娜"),
+            StageCommand::Hide {
+                name: "阿罗娜".to_string(),
+                exit: None
+            }
+        );
+        assert_eq!(
+            parse("hide: 阿罗娜 exit=fade time=1"),
+            StageCommand::Hide {
+                name: "阿罗娜".to_string(),
+                exit: Some(MotionSpec {
+                    kind: MotionKind::Fade,
+                    duration: 1.0,
+                    easing: Easing::Out,
+                }),
+            }
+        );
+        assert_eq!(error("show: 阿罗娜 at=top"), "unknown slot `top`");
+        assert_eq!(
+            error("show: 阿罗娜 enter=spin"),
+            "unknown enter motion `spin`"
+        );
+        assert_eq!(error("show: 阿罗娜 ease=bounce"), "unknown easing `bounce`");
+        assert_eq!(error("hide: 阿罗娜 exit=up"), "unknown exit motion `up`");
+    }
+
+    #[test]
+    fn highlight() {
+        assert_eq!(
+            parse("dim: off"),
+            StageCommand::Highlight {
+                enabled: false,
+                narration_lit: None
+            }
+        );
+        assert_eq!(
+            parse("dim: on narration=dim"),
+            StageCommand::Highlight {
+                enabled: true,
+                narration_lit: Some(false)
+            }
+        );
+        assert_eq!(
+            error("dim: half"),
+            "`dim` expects `on` or `off`, got `half`"
+        );
+        assert_eq!(
+            error("dim: on narration=dark"),
+            "`dim` option `narration` got `dark`"
+        );
+    }
+
+    #[test]
+    fn targeted_commands() {
+        assert_eq!(
+            parse("face: smile"),
+            StageCommand::Track {
+                name: None,
+                animation: Some("smile".to_string()),
+                track: 1,
+                looping: true,
+                mix: None,
+                queue: None,
+            }
+        );
+        assert_eq!(
+            parse("face: 阿罗娜 none track=2 loop=false mix=0.2 queue=1"),
+            StageCommand::Track {
+                name: Some("阿罗娜".to_string()),
+                animation: None,
+                track: 2,
+                looping: false,
+                mix: Some(0.2),
+                queue: Some(1.0),
+            }
+        );
+        assert_eq!(
+            parse("mix: 阿罗娜 0.3"),
+            StageCommand::Mix {
+                name: Some("阿罗娜".to_string()),
+                duration: 0.3
+            }
+        );
+        assert_eq!(
+            parse("blink: off"),
+            StageCommand::Blink {
+                name: None,
+                enabled: false
+            }
+        );
+        assert_eq!(
+            parse("skin: 阿罗娜 casual"),
+            StageCommand::Skin {
+                name: Some("阿罗娜".to_string()),
+                skin: "casual".to_string()
+            }
+        );
+        assert_eq!(
+            parse("attach: halo none"),
+            StageCommand::Attachment {
+                name: None,
+                slot: "halo".to_string(),
+                attachment: None
+            }
+        );
+        assert_eq!(
+            parse("attach: 阿罗娜 halo halo_2"),
+            StageCommand::Attachment {
+                name: Some("阿罗娜".to_string()),
+                slot: "halo".to_string(),
+                attachment: Some("halo_2".to_string())
+            }
+        );
+        assert_eq!(
+            error("face:"),
+            "`face` expects 1 argument(s) after an optional character name"
+        );
+        assert_eq!(
+            error("face: smile track=two"),
+            "`face` option `track` got `two`"
+        );
+        assert_eq!(
+            error("face: smile loop=yes"),
+            "`face` option `loop` expects true or false, got `yes`"
+        );
+        assert_eq!(error("mix: soon"), "`mix` expects seconds, got `soon`");
+        assert_eq!(
+            error("blink: maybe"),
+            "`blink` expects `on` or `off`, got `maybe`"
+        );
+        assert_eq!(
+            error("attach: a b c d"),
+            "`attach` got unexpected argument `d`"
+        );
+    }
+
+    #[test]
+    fn actions_and_animations() {
+        assert_eq!(
+            parse("act: hop"),
+            StageCommand::Motion {
+                name: None,
+                spec: MotionSpec::new(MotionKind::Hop),
+            }
+        );
+        assert_eq!(
+            parse("act: 阿罗娜 closeup scale=1.5 time=0.8 ease=linear"),
+            StageCommand::Motion {
+                name: Some("阿罗娜".to_string()),
+                spec: MotionSpec {
+                    kind: MotionKind::CloseUp {
+                        from: 1.0,
+                        scale: 1.5
+                    },
+                    duration: 0.8,
+                    easing: Easing::Linear,
+                },
+            }
+        );
+        assert_eq!(
+            parse("anim: 阿罗娜 05"),
+            StageCommand::Animation {
+                name: Some("阿罗娜".to_string()),
+                animation: "05".to_string()
+            }
+        );
+        assert_eq!(error("act: spin"), "unknown action `spin`");
+        assert_eq!(error("act:"), "`act` requires an action");
+        assert_eq!(error("anim:"), "`anim` requires an animation name");
+    }
+
+    #[test]
+    fn unknown_key() {
+        assert_eq!(error("zoom: 2"), "unknown tag command `zoom`");
+    }
+
+    #[test]
+    fn bare_tag_only_first() {
+        let speaker_animation = |animation: &str| StageCommand::Animation {
+            name: None,
+            animation: animation.to_string(),
+        };
+        assert_eq!(
+            tags(&["03", "04", "sfx: door", "bad tag", "bgm: stop"]),
+            vec![
+                speaker_animation("03"),
+                StageCommand::Sfx {
+                    name: "door".to_string()
+                },
+                StageCommand::Bgm {
+                    name: None,
+                    fade: 0.0
+                },
+            ]
+        );
+        // 带冒号的 `anim:` 不受位置限制
+        assert_eq!(
+            tags(&["sfx: door", "anim: 03"]),
+            vec![
+                StageCommand::Sfx {
+                    name: "door".to_string()
+                },
+                speaker_animation("03"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+/// 角色名中不应出现的标点，带有这些字符时冒号前的部分视为正文
+const NAME_FORBIDDEN: &[char] = &[
+    '，', '。', '！', '？', '、', '；', '…', '—', '“', '”', '「', '」', '（', '）', ',', '.', '!',
+    '?', ';', '(', ')', '"',
+];
+
+/// 角色名最长字数，超过时冒号前的部分视为正文
+const NAME_MAX_CHARS: usize = 16;
+
+/// 动作描写使用的括号，如 `（从阿罗娜身后探出头）`
+const ACTION_BRACKETS: &[(char, char)] = &[('（', '）'), ('(', ')')];
+
+/// 一行台词拆出的说话人与正文，加载器和运行时共用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpeakerLine {
+    /// 角色名，用于立绘、语音等查找，旁白为 `None`
+    pub speaker: Option<String>,
+    /// `角色名[显示名]:` 给出的对话框显示名
+    pub display_name: Option<String>,
+    pub content: String,
+}
+
+impl SpeakerLine {
+    /// 支持 `旁白`、`角色名: 台词`、`角色名：台词` 和 `角色名[???]: 台词`，
+    /// 半角冒号后须有空格，以免把 `10:30` 之类的正文当作角色名
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        let split = text.char_indices().find_map(|(index, c)| match c {
+            '：' => Some((index, index + c.len_utf8())),
+            ':' => {
+                let rest = &text[index + 1..];
+                (rest.is_empty() || rest.starts_with(char::is_whitespace))
+                    .then_some((index, index + 1))
+            }
+            _ => None,
+        });
+        if let Some((colon, rest)) = split
+            && let Some((speaker, display_name)) = parse_name(&text[..colon])
+        {
+            return Self {
+                speaker: Some(speaker),
+                display_name,
+                content: text[rest..].trim_start().to_string(),
+            };
+        }
+        Self {
+            speaker: None,
+            display_name: None,
+            content: text.to_string(),
+        }
+    }
+
+    pub fn is_narration(&self) -> bool {
+        self.speaker.is_none()
+    }
+
+    /// 对话框中显示的名字
+    pub fn shown_name(&self) -> Option<&String> {
+        self.display_name.as_ref().or(self.speaker.as_ref())
+    }
+}
+
+/// 解析冒号前的部分，返回 (角色名, 显示名)
+fn parse_name(name: &str) -> Option<(String, Option<String>)> {
+    let name = name.trim();
+    let (name, display_name) = match name.strip_suffix(']').and_then(|n| n.split_once('[')) {
+        Some((name, display_name)) => (name.trim(), Some(display_name.trim().to_string())),
+        None => (name, None),
+    };
+    let valid = !name.is_empty()
+        && name.chars().count() <= NAME_MAX_CHARS
+        && !name.contains(NAME_FORBIDDEN)
+        && !name.contains(['[', ']']);
+    valid.then(|| {
+        (
+            name.to_string(),
+            display_name.filter(|display_name| !display_name.is_empty()),
+        )
+    })
+}
+
+/// 每个字符是否位于括号动作描写中（含括号本身），未闭合的括号一直延续到末尾，
+/// 逐字显示时对已显示的前缀调用也能得到一致的结果
+pub fn action_mask(text: &str) -> Vec<bool> {
+    let mut closing = None;
+    text.chars()
+        .map(|c| match closing {
+            Some(close) => {
+                if c == close {
+                    closing = None;
+                }
+                true
+            }
+            None => match ACTION_BRACKETS.iter().find(|(open, _)| *open == c) {
+                Some(&(_, close)) => {
+                    closing = Some(close);
+                    true
+                }
+                None => false,
+            },
+        })
+        .collect()
+}
+
+/// 没有 `# voice:` 标签时按约定生成的语音名 `<角色>/<knot>_<序号>`
+pub fn voice_name(speaker: &str, knot: &str, index: usize) -> String {
+    format!("{}/{}_{}", speaker, knot, index)
+}
+
+/// 去掉 ink 语法，只留下会显示的文本；knot 声明、跳转、逻辑和注释行返回 `None`
+pub(crate) fn script_text(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let skipped = ["=", "->", "~", "//", "VAR ", "CONST ", "INCLUDE ", "TODO"];
+    if skipped.iter().any(|prefix| line.starts_with(prefix)) {
+        return None;
+    }
+    // 选项和汇合点的标记，以及选项中只在选择时显示的方括号部分
+    let mut text = line.trim_start_matches(['*', '+', '-', ' ', '\t']);
+    if text.starts_with('[') {
+        text = text.split_once(']').map_or("", |(_, rest)| rest);
+    }
+    let text = text.split('#').next().unwrap_or_default();
+    let text = text.split("->").next().unwrap_or_default().trim();
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(speaker: Option<&str>, display_name: Option<&str>, content: &str) -> SpeakerLine {
+        SpeakerLine {
+            speaker: speaker.map(str::to_string),
+            display_name: display_name.map(str::to_string),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn narration() {
+        assert_eq!(
+            SpeakerLine::parse("啊，当然，工作完成之后再说！\n"),
+            line(None, None, "啊，当然，工作完成之后再说！")
+        );
+        assert!(SpeakerLine::parse("不过……我也很期待。").is_narration());
+    }
+
+    #[test]
+    fn half_width_colon() {
+        assert_eq!(
+            SpeakerLine::parse("阿罗娜: Sensei，早上好。 \n"),
+            line(Some("阿罗娜"), None, "Sensei，早上好。")
+        );
+    }
+
+    #[test]
+    fn full_width_colon() {
+        assert_eq!(
+            SpeakerLine::parse("普拉娜：耶！Sensei最好了的说！"),
+            line(Some("普拉娜"), None, "耶！Sensei最好了的说！")
+        );
+    }
+
+    #[test]
+    fn display_name_override() {
+        let parsed = SpeakerLine::parse("桐藤渚[???]: 请稍等。");
+        assert_eq!(parsed, line(Some("桐藤渚"), Some("???"), "请稍等。"));
+        assert_eq!(parsed.shown_name().map(String::as_str), Some("???"));
+        assert_eq!(
+            SpeakerLine::parse("阿罗娜[]: 嗯。"),
+            line(Some("阿罗娜"), None, "嗯。")
+        );
+    }
+
+    #[test]
+    fn colons_inside_dialogue() {
+        assert_eq!(
+            SpeakerLine::parse("早濑优香: 会议定在 10:30，地点：会议室。"),
+            line(Some("早濑优香"), None, "会议定在 10:30，地点：会议室。")
+        );
+        assert_eq!(
+            SpeakerLine::parse("现在是 10:30。"),
+            line(None, None, "现在是 10:30。")
+        );
+        assert_eq!(
+            SpeakerLine::parse("她看了看表，说道：“走吧。”"),
+            line(None, None, "她看了看表，说道：“走吧。”")
+        );
+    }
+
+    #[test]
+    fn leading_action_is_not_a_name() {
+        assert_eq!(
+            SpeakerLine::parse("（从阿罗娜身后探出头）是的说：嗯！"),
+            line(None, None, "（从阿罗娜身后探出头）是的说：嗯！")
+        );
+        assert_eq!(
+            SpeakerLine::parse("普拉娜: （从阿罗娜身后探出头）是的说！"),
+            line(Some("普拉娜"), None, "（从阿罗娜身后探出头）是的说！")
+        );
+    }
+
+    #[test]
+    fn action_text_is_marked() {
+        let mask = action_mask("嗯（点头）好(ok)");
+        assert_eq!(
+            mask,
+            vec![false, true, true, true, true, false, true, true, true, true]
+        );
+        // 逐字显示到一半时，未闭合的括号之后仍视为动作
+        assert_eq!(action_mask("嗯（点"), vec![false, true, true]);
+    }
+}
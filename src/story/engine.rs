@@ -0,0 +1,444 @@
+use inkling::{Prompt, Story, read_story_from_string};
+use std::collections::VecDeque;
+use std::fs;
+
+use crate::story::{
+    ROOT_KNOT, ReadTracker, SceneIndex, SpeakerLine, StageCommand, StoryError, StoryResult,
+    voice_name,
+};
+
+/// 一句解析后的台词，不含任何渲染相关的类型
+#[derive(Debug, Clone, PartialEq)]
+pub struct DialogueLine {
+    pub speaker: Option<String>,
+    /// `角色名[显示名]:` 给出的对话框显示名
+    pub display_name: Option<String>,
+    pub content: String,
+    /// 行首无冒号标签给出的动画名
+    pub animation: Option<String>,
+    /// 已读记录的键，见 [`ReadTracker::key`]
+    pub key: String,
+    /// 语音名：`# voice:` 标签指定，否则有说话人时按 `<角色>/<knot>_<序号>` 生成，
+    /// 序号为台词在所在 knot 中的位置（从 0 开始），与从哪个分支到达无关
+    pub voice: Option<String>,
+    /// 语音是否由标签明确指定
+    pub voice_tagged: bool,
+}
+
+impl DialogueLine {
+    fn parse(
+        line: &inkling::Line,
+        key: String,
+        knot_line: &(String, usize),
+    ) -> (Self, Vec<StageCommand>) {
+        let commands = StageCommand::parse_tags(line);
+        let SpeakerLine {
+            speaker,
+            display_name,
+            content,
+        } = SpeakerLine::parse(&line.text);
+        let animation = commands.iter().find_map(|c| match c {
+            StageCommand::Animation {
+                name: None,
+                animation,
+            } => Some(animation.clone()),
+            _ => None,
+        });
+        let tagged = commands.iter().find_map(|c| match c {
+            StageCommand::Voice { name } => Some(name.clone()),
+            _ => None,
+        });
+        let voice_tagged = tagged.is_some();
+        let voice = tagged.or_else(|| {
+            speaker
+                .as_ref()
+                .map(|speaker| voice_name(speaker, &knot_line.0, knot_line.1))
+        });
+        (
+            Self {
+                speaker,
+                display_name,
+                content,
+                animation,
+                key,
+                voice,
+                voice_tagged,
+            },
+            commands,
+        )
+    }
+}
+
+/// 故事引擎发出的事件，渲染层按顺序消费
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoryEvent {
+    Line(DialogueLine),
+    /// 属于上一句台词的演出指令，紧跟在 `Line` 之后
+    Command(StageCommand),
+    Choices(Vec<String>),
+    End,
+}
+
+/// 只负责推进 ink 故事的引擎，不依赖 macroquad，可在无窗口环境下测试
+pub struct StoryEngine {
+    pub(crate) story: Story,
+    pub(crate) line_buffer: Vec<inkling::Line>,
+    pub current_choices: Vec<inkling::Choice>,
+    pub is_choosing: bool,
+    pub is_choose: bool,
+    pub story_ended: bool,
+    pub story_end: bool,
+    /// 上一句台词所在的 knot 及下一句在其中的序号，用于已读记录、语音命名和分段载入
+    pub(crate) knot_line: (String, usize),
+    /// 脚本中各 knot 的台词，用于找出每句台词所在的 knot
+    scenes: SceneIndex,
+    pub(crate) events: VecDeque<StoryEvent>,
+}
+
+impl std::fmt::Debug for StoryEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "is_choose = {}, is_choosing = {}, is_end = {}, is_ended = {}",
+            self.is_choose, self.is_choosing, self.story_end, self.story_ended,
+        )
+    }
+}
+
+impl StoryEngine {
+    pub fn from_ink(content: &str) -> StoryResult<Self> {
+        let mut story = read_story_from_string(content)
+            .map_err(|e| StoryError::story(format!("Can not read story. {:?}", e)))?;
+        // 开始故事
+        story.start().map_err(StoryError::story)?;
+        Ok(Self {
+            story,
+            line_buffer: Vec::new(),
+            current_choices: Vec::new(),
+            is_choosing: false,
+            is_choose: false,
+            story_ended: false,
+            story_end: false,
+            knot_line: (ROOT_KNOT.to_string(), 0),
+            scenes: SceneIndex::analyse(content),
+            events: VecDeque::new(),
+        })
+    }
+
+    pub fn load(story_path: &str) -> StoryResult<Self> {
+        let content = fs::read_to_string(story_path).map_err(|e| StoryError::io(story_path, e))?;
+        Self::from_ink(&content)
+    }
+
+    /// 推进到下一句台词、选项或结局，产生的事件通过 [`StoryEngine::poll_event`] 取出
+    pub fn advance(&mut self) -> StoryResult<()> {
+        if self.is_choosing || self.story_ended {
+            return Ok(());
+        }
+        // 还有待选的选项时不能继续 resume
+        if self.line_buffer.is_empty() && !self.is_choose {
+            if self.story_end {
+                self.story_ended = true;
+                self.events.push_back(StoryEvent::End);
+                return Ok(());
+            }
+            let prompt = self
+                .story
+                .resume(&mut self.line_buffer)
+                .map_err(StoryError::story)?;
+            match prompt {
+                Prompt::Done => {
+                    println!("Story will be Done");
+                    self.story_end = true;
+                }
+                Prompt::Choice(choices) => {
+                    println!("Story will have choices");
+                    self.is_choose = true;
+                    self.current_choices = choices;
+                }
+            }
+        }
+
+        if !self.line_buffer.is_empty() {
+            let line = self.line_buffer.remove(0);
+            // 一次 resume 可能跨过多个 knot，按台词文本在脚本中找出所在的 knot，
+            // 找不到时（如含有变量的台词）沿用上一句的 knot 顺延
+            let (knot, index) = self
+                .scenes
+                .locate(&self.knot_line.0, self.knot_line.1, &line.text)
+                .unwrap_or_else(|| self.knot_line.clone());
+            let key = ReadTracker::key(&knot, index, &line.text);
+            let (line, commands) = DialogueLine::parse(&line, key, &(knot.clone(), index));
+            self.knot_line = (knot, index + 1);
+            self.events.push_back(StoryEvent::Line(line));
+            self.events
+                .extend(commands.into_iter().map(StoryEvent::Command));
+        } else if self.is_choose {
+            self.is_choosing = true;
+            self.is_choose = false;
+            self.events.push_back(StoryEvent::Choices(
+                self.current_choices
+                    .iter()
+                    .map(|c| c.text.clone())
+                    .collect(),
+            ));
+        } else if self.story_end {
+            // 故事直接结束，没有剩余台词
+            self.story_ended = true;
+            self.events.push_back(StoryEvent::End);
+        }
+        Ok(())
+    }
+
+    /// 选择选项并推进，返回所选选项的文本
+    pub fn choose(&mut self, choice_index: usize) -> StoryResult<Option<String>> {
+        if !self.is_choosing || choice_index >= self.current_choices.len() {
+            return Ok(None);
+        }
+        self.story
+            .make_choice(choice_index)
+            .map_err(StoryError::story)?;
+        let text = self.current_choices[choice_index].text.clone();
+        self.current_choices.clear();
+        self.is_choosing = false;
+        self.advance()?;
+        Ok(Some(text))
+    }
+
+    /// 脚本中各 knot 的台词与资源
+    pub fn scenes(&self) -> &SceneIndex {
+        &self.scenes
+    }
+
+    /// 是否还有没取出的事件
+    pub fn has_events(&self) -> bool {
+        !self.events.is_empty()
+    }
+
+    pub fn poll_event(&mut self) -> Option<StoryEvent> {
+        self.events.pop_front()
+    }
+
+    /// 当前位置的 knot 与 stitch
+    pub fn location(&self) -> (String, Option<String>) {
+        self.story.get_current_location()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const STORY_PATH: &str = "assets/story.ink";
+
+    /// 不断推进直到出现选项或结局，返回期间的全部事件
+    fn run(engine: &mut StoryEngine) -> Vec<StoryEvent> {
+        let mut events = Vec::new();
+        for _ in 0..1000 {
+            engine.advance().unwrap();
+            while let Some(event) = engine.poll_event() {
+                events.push(event);
+            }
+            if engine.is_choosing || engine.story_ended {
+                return events;
+            }
+        }
+        panic!("story did not reach a choice or the end");
+    }
+
+    fn lines(events: &[StoryEvent]) -> Vec<&DialogueLine> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                StoryEvent::Line(line) => Some(line),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn contains_line(events: &[StoryEvent], speaker: &str, text: &str) -> bool {
+        lines(events)
+            .iter()
+            .any(|l| l.speaker.as_deref() == Some(speaker) && l.content.contains(text))
+    }
+
+    #[test]
+    fn opening_lines_until_choice() {
+        let mut engine = StoryEngine::load(STORY_PATH).unwrap();
+        let events = run(&mut engine);
+
+        let first = lines(&events)[0];
+        assert_eq!(first.speaker.as_deref(), Some("阿罗娜"));
+        assert!(first.content.starts_with("Sensei，早上好。"));
+        assert_eq!(first.animation.as_deref(), Some("00"));
+        assert_eq!(
+            events[1],
+            StoryEvent::Command(StageCommand::Animation {
+                name: None,
+                animation: "00".to_string()
+            })
+        );
+
+        let narration = lines(&events)
+            .into_iter()
+            .find(|l| l.content.contains("工作完成之后再说"))
+            .unwrap();
+        assert_eq!(narration.speaker, None);
+
+        match events.last().unwrap() {
+            StoryEvent::Choices(choices) => {
+                assert_eq!(choices.len(), 2);
+                assert!(choices[0].contains("先处理完手头这份报告吧"));
+                assert!(choices[1].contains("现在就去吧"));
+            }
+            other => panic!("expected choices, got {:?}", other),
+        }
+        assert!(!engine.story_ended);
+    }
+
+    #[test]
+    fn advance_is_ignored_while_choosing() {
+        let mut engine = StoryEngine::load(STORY_PATH).unwrap();
+        run(&mut engine);
+        engine.advance().unwrap();
+        assert!(engine.is_choosing);
+        assert_eq!(engine.poll_event(), None);
+        assert_eq!(engine.choose(5).unwrap(), None);
+    }
+
+    #[test]
+    fn report_branch() {
+        let mut engine = StoryEngine::load(STORY_PATH).unwrap();
+        run(&mut engine);
+        let chosen = engine.choose(0).unwrap().unwrap();
+        assert!(chosen.contains("先处理完手头这份报告吧"));
+
+        let mut events = Vec::new();
+        while let Some(event) = engine.poll_event() {
+            events.push(event);
+        }
+        events.extend(run(&mut engine));
+
+        assert!(contains_line(&events, "阿罗娜", "我会在一旁待机"));
+        assert!(contains_line(&events, "普拉娜", "二十分钟后"));
+        assert!(!contains_line(&events, "普拉娜", "Sensei最好了"));
+        assert!(contains_line(&events, "早濑优香", "活动申请表格"));
+        assert_eq!(events.last(), Some(&StoryEvent::End));
+        assert!(engine.story_ended);
+    }
+
+    #[test]
+    fn noreport_branch() {
+        let mut engine = StoryEngine::load(STORY_PATH).unwrap();
+        run(&mut engine);
+        engine.choose(1).unwrap().unwrap();
+
+        let mut events = Vec::new();
+        while let Some(event) = engine.poll_event() {
+            events.push(event);
+        }
+        events.extend(run(&mut engine));
+
+        assert!(contains_line(&events, "普拉娜", "Sensei最好了"));
+        assert!(contains_line(&events, "阿罗娜", "您太宠她了"));
+        assert!(!contains_line(&events, "阿罗娜", "我会在一旁待机"));
+        assert!(contains_line(&events, "黑见芹香", "高级蜂蜜"));
+        assert_eq!(events.last(), Some(&StoryEvent::End));
+        assert!(engine.story_ended);
+    }
+
+    /// 选择 `choice` 后直到结局的全部事件
+    fn run_branch(choice: usize) -> Vec<StoryEvent> {
+        let mut engine = StoryEngine::load(STORY_PATH).unwrap();
+        let mut events = run(&mut engine);
+        engine.choose(choice).unwrap().unwrap();
+        while let Some(event) = engine.poll_event() {
+            events.push(event);
+        }
+        events.extend(run(&mut engine));
+        events
+    }
+
+    fn voice_of<'a>(events: &'a [StoryEvent], text: &str) -> Option<&'a str> {
+        lines(events)
+            .into_iter()
+            .find(|l| l.content.contains(text))
+            .and_then(|l| l.voice.as_deref())
+    }
+
+    #[test]
+    fn voice_names_follow_convention() {
+        let mut engine = StoryEngine::load(STORY_PATH).unwrap();
+        let events = run(&mut engine);
+        let lines = lines(&events);
+        assert_eq!(lines[0].voice.as_deref(), Some("阿罗娜/$ROOT$_0"));
+        assert_eq!(lines[3].voice.as_deref(), Some("普拉娜/$ROOT$_3"));
+        assert!(!lines[0].voice_tagged);
+        let narration = lines.iter().find(|l| l.speaker.is_none()).unwrap();
+        assert_eq!(narration.voice, None);
+    }
+
+    #[test]
+    fn voice_names_follow_the_knot_of_each_line() {
+        let report = run_branch(0);
+        let noreport = run_branch(1);
+        assert_eq!(
+            voice_of(&report, "需要我帮您整理咖啡桌上的文件吗"),
+            Some("阿罗娜/report_1")
+        );
+        assert_eq!(voice_of(&noreport, "您太宠她了"), Some("阿罗娜/noreport_1"));
+        // 两个分支汇合后的台词语音名相同
+        for events in [&report, &noreport] {
+            assert_eq!(voice_of(events, "卡班卡共和国风味"), Some("普拉娜/end_0"));
+            assert_eq!(voice_of(events, "高级蜂蜜"), Some("黑见芹香/end_2"));
+            assert_eq!(voice_of(events, "今天也辛苦您了"), Some("阿罗娜/end_8"));
+        }
+        for events in [&report, &noreport] {
+            let voices: Vec<&str> = lines(events)
+                .iter()
+                .filter_map(|l| l.voice.as_deref())
+                .collect();
+            let unique: HashSet<&&str> = voices.iter().collect();
+            assert_eq!(unique.len(), voices.len());
+        }
+    }
+
+    #[test]
+    fn line_keys_do_not_depend_on_the_branch() {
+        let key_of = |events: &[StoryEvent], text: &str| {
+            lines(events)
+                .into_iter()
+                .find(|l| l.content.contains(text))
+                .map(|l| l.key.clone())
+                .unwrap()
+        };
+        let report = run_branch(0);
+        let noreport = run_branch(1);
+        for text in ["卡班卡共和国风味", "活动申请表格", "大家都很开心"] {
+            assert_eq!(key_of(&report, text), key_of(&noreport, text));
+        }
+        assert!(key_of(&report, "活动申请表格").starts_with("end:7:"));
+        assert_ne!(
+            key_of(&report, "需要我帮您整理"),
+            key_of(&noreport, "您太宠她了")
+        );
+    }
+
+    #[test]
+    fn line_keys_are_stable() {
+        let mut first = StoryEngine::load(STORY_PATH).unwrap();
+        let mut second = StoryEngine::load(STORY_PATH).unwrap();
+        let first_keys: Vec<String> = lines(&run(&mut first))
+            .iter()
+            .map(|l| l.key.clone())
+            .collect();
+        let second_keys: Vec<String> = lines(&run(&mut second))
+            .iter()
+            .map(|l| l.key.clone())
+            .collect();
+        assert_eq!(first_keys, second_keys);
+        let unique: HashSet<&String> = first_keys.iter().collect();
+        assert_eq!(unique.len(), first_keys.len());
+    }
+}
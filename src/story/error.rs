@@ -0,0 +1,131 @@
+use std::fmt;
+
+use crate::spine::SpineError;
+
+/// 错误提示最多保留的条数
+pub const ERROR_LOG_CAPACITY: usize = 8;
+
+/// 故事模块载入资源与运行时的错误
+#[derive(Debug)]
+pub enum StoryError {
+    /// 文件读写失败
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    /// 文件内容无法解析
+    Parse { path: String, message: String },
+    /// 图片缺失或格式不支持
+    Texture { path: String, message: String },
+    /// 音频缺失或无法解码
+    Sound { path: String, message: String },
+    /// 角色骨骼载入失败
+    Character { name: String, source: SpineError },
+    /// 清单或故事中引用的资源没有载入
+    MissingAsset { kind: &'static str, name: String },
+    /// 角色的动画、皮肤或附件设置失败
+    Animation { name: String, source: SpineError },
+    /// 存档读写失败
+    Save { slot: usize, message: String },
+    /// ink 故事编译或运行出错
+    Story { message: String },
+}
+
+pub type StoryResult<T> = std::result::Result<T, StoryError>;
+
+impl StoryError {
+    pub fn io(path: &str, source: std::io::Error) -> Self {
+        StoryError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn missing(kind: &'static str, name: &str) -> Self {
+        StoryError::MissingAsset {
+            kind,
+            name: name.to_string(),
+        }
+    }
+
+    pub fn story(message: impl fmt::Display) -> Self {
+        StoryError::Story {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for StoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoryError::Io { path, source } => write!(f, "Can not access {}. {}", path, source),
+            StoryError::Parse { path, message } => {
+                write!(f, "Can not parse {}. {}", path, message)
+            }
+            StoryError::Texture { path, message } => {
+                write!(f, "Can not load texture {}. {}", path, message)
+            }
+            StoryError::Sound { path, message } => {
+                write!(f, "Can not load sound {}. {}", path, message)
+            }
+            StoryError::Character { name, source } => {
+                write!(f, "Can not load character {}. {}", name, source)
+            }
+            StoryError::MissingAsset { kind, name } => write!(f, "Missing {} {}.", kind, name),
+            StoryError::Animation { name, source } => write!(f, "{}: {}", name, source),
+            StoryError::Save { slot, message } => write!(f, "Slot {}: {}", slot, message),
+            StoryError::Story { message } => write!(f, "Story error. {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StoryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoryError::Io { source, .. } => Some(source),
+            StoryError::Character { source, .. } => Some(source),
+            StoryError::Animation { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// 运行中出现的错误，显示在画面上而不是让游戏崩溃
+#[derive(Debug, Clone, Default)]
+pub struct ErrorLog {
+    entries: Vec<String>,
+    /// 提示是否显示，玩家关闭后有新错误时再次显示
+    pub visible: bool,
+}
+
+impl ErrorLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一条错误，相同的错误只记录一次，超过容量时丢弃最早的
+    pub fn push(&mut self, error: impl fmt::Display) {
+        let message = error.to_string();
+        if self.entries.contains(&message) {
+            return;
+        }
+        println!("Error: {}", message);
+        if self.entries.len() >= ERROR_LOG_CAPACITY {
+            self.entries.remove(0);
+        }
+        self.entries.push(message);
+        self.visible = true;
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn dismiss(&mut self) {
+        self.visible = false;
+    }
+}
@@ -1,178 +1,571 @@
 use crate::story::sprite::SpriteAtlas;
 use crate::story::ui::*;
-use crate::story::{TextureManager, character::CharacterManager};
-pub use anyhow::Result;
-pub use inkling::{Prompt, Story, read_story_from_string};
-use macroquad::audio::Sound;
+use crate::story::{
+    AudioManager, BackgroundView, ChoiceMenu, DialogueLine, ErrorLog, History, MotionKind,
+    MotionSpec, PlayMode, Playback, ReadTracker, SaveManager, SaveMenu, SceneStreamer,
+    StageCommand, StoryEngine, StoryEvent, StoryResult, TextReveal, TextureManager, Transition,
+    TransitionKind, VIRTUAL_HEIGHT, VIRTUAL_WIDTH, begin_canvas, character::CharacterManager,
+    end_canvas,
+};
 use macroquad::prelude::*;
-use regex::bytes::Regex;
-pub use std::fs;
-use std::io::Read;
+use std::collections::{HashMap, HashSet};
 
 pub async fn draw_frame(state: &GameState<'_>) {
-    if let Some(background) = &state.get_background() {
-        draw_background(background);
-    } else {
-        clear_background(Color::new(0.1, 0.1, 0.2, 1.0));
-    }
+    // 按虚拟分辨率绘制，窗口比例不同时留黑边
+    begin_canvas();
+    draw_scene_background(state);
 
-    let gradient_height = screen_height() * 0.25; // 遮罩占屏幕1/4高度
+    let gradient_height = VIRTUAL_HEIGHT * 0.25; // 遮罩占屏幕1/4高度
 
+    // 缺少选项框纹理时用图集九宫格底板，图集也没有时以纯色矩形代替
     let texture = state
         .textures
         .as_ref()
-        .unwrap()
-        .get("chioce_box")
-        .unwrap_or_else(|| panic!("can not got chiocebox texture. "));
+        .and_then(|textures| textures.get("chioce_box"));
 
-    if let Some(character) = &state.speak_state.name {
-        for i in state.character_manager.get_meshs(character) {
-            draw_mesh(&i);
+    for meshs in state
+        .character_manager
+        .stage_meshs(state.speak_state.name.as_deref())
+    {
+        for i in meshs {
+            draw_mesh(i);
         }
     }
 
     if !state.speak_state.content.is_empty() {
         draw_dialog_box(
             gradient_height,
-            state.speak_state.name.as_ref(),
-            &state.speak_state.content,
+            state.speak_state.shown_name(),
+            state.reveal.visible_text(),
             state.font.as_ref(),
         );
     }
 
     // 显示选项（如果有）
-    if state.is_choosing {
+    if state.engine.is_choosing {
         draw_chioce(
-            &state.current_choices,
+            &state.engine.current_choices,
+            &state.choice_menu,
             texture,
+            state.atlas.as_ref(),
             state.material,
             state.font.as_ref(),
         );
     }
+
+    if let Some(color) = state.transition.as_ref().and_then(|t| t.overlay()) {
+        draw_rectangle(0.0, 0.0, VIRTUAL_WIDTH, VIRTUAL_HEIGHT, color);
+    }
+
+    draw_play_mode(&state.playback, state.font.as_ref());
+
+    if state.history.is_open {
+        draw_backlog(&state.history, state.font.as_ref());
+    }
+
+    draw_save_menu(&state.save_menu, state.font.as_ref());
+
+    if state.errors.visible {
+        draw_error_overlay(&state.errors, state.font.as_ref());
+    }
+    end_canvas();
 }
+fn draw_background_or_clear(texture: Option<&Texture2D>, view: &BackgroundView) {
+    if let Some(background) = texture {
+        // contain / fixed 模式留出的边框为黑色
+        draw_rectangle(0.0, 0.0, VIRTUAL_WIDTH, VIRTUAL_HEIGHT, BLACK);
+        draw_background(background, view, WHITE, 1.0);
+    } else {
+        draw_rectangle(
+            0.0,
+            0.0,
+            VIRTUAL_WIDTH,
+            VIRTUAL_HEIGHT,
+            Color::new(0.1, 0.1, 0.2, 1.0),
+        );
+    }
+}
+
+/// 绘制背景，转场期间按进度混合新旧背景
+fn draw_scene_background(state: &GameState<'_>) {
+    let current = state.get_background();
+    let view = &state.background_view;
+    let Some(transition) = &state.transition else {
+        draw_background_or_clear(current, view);
+        return;
+    };
+    let old = transition
+        .from
+        .as_deref()
+        .and_then(|name| state.textures.as_ref()?.get(name));
+    if transition.shows_old_background() {
+        draw_background_or_clear(old, &transition.from_view);
+        return;
+    }
+    let progress = transition.progress();
+    match transition.spec.kind {
+        TransitionKind::Dissolve => {
+            draw_background_or_clear(old, &transition.from_view);
+            if let Some(current) = current {
+                draw_background(current, view, Color::new(1.0, 1.0, 1.0, progress), 1.0);
+            }
+        }
+        TransitionKind::Wipe => {
+            draw_background_or_clear(old, &transition.from_view);
+            if let Some(current) = current {
+                draw_background(current, view, WHITE, progress);
+            }
+        }
+        TransitionKind::Fade | TransitionKind::Flash => draw_background_or_clear(current, view),
+    }
+}
+
 pub struct GameState<'a> {
-    story: Story,
+    pub engine: StoryEngine,
     pub speak_state: SpeakerState,
-    line_buffer: Vec<inkling::Line>,
-    pub current_choices: Vec<inkling::Choice>,
-    pub is_choosing: bool,
-    pub is_choose: bool,
-    pub story_ended: bool,
-    pub story_end: bool,
     pub should_continue: bool,
     pub background: Option<String>,
+    /// 当前背景的缩放方式与镜头动画
+    pub background_view: BackgroundView,
+    /// 清单中各背景的默认显示方式
+    pub background_views: HashMap<String, BackgroundView>,
     pub character_manager: CharacterManager,
     pub font: Option<Font>,
     pub textures: Option<TextureManager>,
     pub material: Option<&'a Material>,
-    pub sound: Option<Sound>,
+    pub audio: AudioManager,
     pub atlas: Option<SpriteAtlas>,
+    pub save_manager: SaveManager,
+    pub save_menu: SaveMenu,
+    pub history: History,
+    /// 进行中的转场
+    pub transition: Option<Transition>,
+    pub reveal: TextReveal,
+    pub choice_menu: ChoiceMenu,
+    pub playback: Playback,
+    pub read_lines: ReadTracker,
+    /// 当前台词在显示前是否已读
+    pub current_line_read: bool,
+    /// 已报告过缺失的语音，每个只报告一次
+    pub missing_voices: HashSet<String>,
+    /// 载入和运行中出现的错误，显示为画面上的提示
+    pub errors: ErrorLog,
+    /// 按 knot 分段载入资源的状态
+    pub streamer: SceneStreamer,
 }
 
 impl<'a> std::fmt::Debug for GameState<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "is_choose = {}, is_choosing = {}, is_end = {}, is_ended = {}, should_continue = {}",
-            self.is_choose,
-            self.is_choosing,
-            self.story_end,
-            self.story_ended,
-            self.should_continue
+            "{:?}, should_continue = {}",
+            self.engine, self.should_continue
         )
     }
 }
 
 impl<'a> GameState<'a> {
-    pub fn new() -> Result<Self> {
+    pub fn new(story_path: &str) -> StoryResult<Self> {
         // 读取故事文件
-        let story_content = fs::read_to_string("assets/story.ink")?;
-        let mut story = read_story_from_string(&story_content).unwrap();
-        let line_buffer = Vec::new();
+        let engine = StoryEngine::load(story_path)?;
         let character_manager = CharacterManager::new();
 
-        // 开始故事
-        story.start()?;
-
-        // 获取初始内容
-        let mut state = Self {
-            story,
+        let state = Self {
+            engine,
             speak_state: SpeakerState::new(),
-            line_buffer,
-            current_choices: Vec::new(),
-            is_choosing: false,
-            is_choose: false,
-            story_ended: false,
-            story_end: false,
             should_continue: true,
             background: None,
+            background_view: BackgroundView::default(),
+            background_views: HashMap::new(),
             // material: material,
             character_manager: character_manager,
             font: None,
             textures: None,
             material: None,
-            sound: None,
+            audio: AudioManager::new(),
             atlas: None,
+            save_manager: SaveManager::default(),
+            save_menu: SaveMenu::new(),
+            history: History::default(),
+            transition: None,
+            reveal: TextReveal::default(),
+            choice_menu: ChoiceMenu::default(),
+            playback: Playback::default(),
+            read_lines: ReadTracker::new(),
+            current_line_read: false,
+            missing_voices: HashSet::new(),
+            errors: ErrorLog::new(),
+            streamer: SceneStreamer::default(),
         };
 
-        // 处理初始内容
-        state.advance_story()?;
-
         Ok(state)
     }
     fn get_background(&self) -> Option<&Texture2D> {
         self.textures
-            .as_ref()
-            .unwrap()
-            .get(self.background.as_ref().unwrap().as_str())
+            .as_ref()?
+            .get(self.background.as_ref()?.as_str())
     }
 
-    pub fn advance_story(&mut self) -> Result<()> {
-        // 推进故事
-        if self.line_buffer.is_empty() {
-            if self.story_end {
-                self.story_ended = true;
+    /// 每帧更新与渲染无关的状态
+    pub fn update(&mut self, delta_time: f32) -> StoryResult<()> {
+        self.reveal.update(delta_time);
+        self.audio.update(delta_time);
+        self.read_lines.update(delta_time);
+        self.background_view.update(delta_time);
+        // 逐字显示或语音播放期间说话角色动嘴
+        let talking = !self.reveal.is_complete() || self.audio.is_voice_playing();
+        self.character_manager
+            .update_lip_sync(self.speak_state.name.as_deref(), talking);
+        if let Some(transition) = &mut self.transition {
+            // 快进时转场立即结束
+            if self.playback.is_skipping() {
+                transition.finish();
+            }
+            transition.update(delta_time);
+            if transition.is_finished() {
+                self.transition = None;
+            }
+        }
+        if self.engine.is_choosing {
+            self.choice_menu.update(delta_time);
+        }
+        self.update_playback(delta_time)
+    }
+
+    /// 自动播放与快进，两者遇到选项都会停下
+    fn update_playback(&mut self, delta_time: f32) -> StoryResult<()> {
+        if self.engine.is_choosing || self.engine.story_ended {
+            if self.playback.mode != PlayMode::Normal {
+                self.playback.stop();
+            }
+            return Ok(());
+        }
+        if self.history.is_open
+            || self.save_menu.is_open()
+            || self.transition.is_some()
+            || !self.should_continue
+        {
+            self.playback.reset_timer();
+            return Ok(());
+        }
+        if self.playback.is_skipping() {
+            if !self.playback.skip_unread && !self.current_line_read {
+                self.playback.stop();
                 return Ok(());
             }
-            match self.story.resume(&mut self.line_buffer)? {
-                Prompt::Done => {
-                    println!("Story will be Done");
-                    self.story_end = true;
-                }
-                Prompt::Choice(choices) => {
-                    println!("Story will have choices");
-                    self.is_choose = true;
-                    self.current_choices = choices;
-                }
+            if self.playback.tick(delta_time, self.playback.skip_interval) {
+                self.reveal.complete();
+                self.advance_or_complete()?;
+            }
+        } else if self.playback.mode == PlayMode::Auto {
+            // 等语音播完再开始计时
+            if !self.reveal.is_complete() || self.audio.is_voice_playing() {
+                self.playback.reset_timer();
+                return Ok(());
             }
+            let delay = self.playback.auto_delay(&self.speak_state.content);
+            if self.playback.tick(delta_time, delay) {
+                self.advance_or_complete()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 点击或空格：台词未显示完时先补全，已显示完再推进故事
+    pub fn advance_or_complete(&mut self) -> StoryResult<()> {
+        if !self.reveal.is_complete() {
+            self.reveal.complete();
+            return Ok(());
         }
+        self.should_continue = false;
+        self.audio.stop_voice();
+        self.advance_story()
+    }
 
-        if !self.line_buffer.is_empty() {
-            self.update_display_text();
-            self.should_continue = true;
-        } else if self.is_choose {
-            println!("is choosed");
-            self.is_choosing = true;
-            self.is_choose = false;
+    pub fn advance_story(&mut self) -> StoryResult<()> {
+        // 上一句还在等资源时不再推进
+        if self.engine.has_events() {
+            return Ok(());
         }
+        self.engine.advance()?;
+        self.process_events();
         Ok(())
     }
 
-    fn update_display_text(&mut self) {
-        self.speak_state.content.clear();
+    /// 消费引擎产生的事件并更新画面状态。新台词所在的 knot 还没分段载入时先不处理，
+    /// 由主循环在 [`stream_assets`](crate::story::stream_assets) 之后再次调用，
+    /// 保证台词的演出指令能找到资源
+    pub fn process_events(&mut self) {
+        if !self.streamer.is_ready(&self.engine.knot_line.0) {
+            return;
+        }
+        while let Some(event) = self.engine.poll_event() {
+            debug!("{:?}", event);
+            match event {
+                StoryEvent::Line(line) => self.update_display_text(line),
+                StoryEvent::Command(command) => self.execute_command(&command),
+                StoryEvent::Choices(_) => {
+                    println!("is choosed");
+                    self.choice_menu.reset();
+                }
+                StoryEvent::End => println!("Story ended"),
+            }
+        }
+    }
+
+    fn update_display_text(&mut self, line: DialogueLine) {
+        self.current_line_read = self.read_lines.mark_read(line.key.clone());
+        let voice = self.resolve_voice(&line);
+        self.speak_state = SpeakerState::from_line(line);
+        self.speak_state.voice = voice;
+        // 快进时不播放语音
+        if let Some(voice) = &self.speak_state.voice
+            && !self.playback.is_skipping()
+        {
+            self.audio.play_voice(voice);
+        }
+        self.speak_state.content = self.reveal.start(&self.speak_state.content);
+        self.history.record_line(&self.speak_state);
+        if let Some(name) = &self.speak_state.name {
+            self.character_manager.show_auto(name);
+        }
+        self.character_manager
+            .set_speaker(self.speak_state.name.as_deref());
+        self.should_continue = true;
+    }
+
+    /// 找到台词对应的已载入语音；缺失时每个文件只报告一次，
+    /// 按约定查找且该角色没有语音目录时视为无语音，不报告
+    fn resolve_voice(&mut self, line: &DialogueLine) -> Option<String> {
+        let voice = line.voice.as_ref()?;
+        if self.audio.contains(voice) {
+            return Some(voice.clone());
+        }
+        let expected = line.voice_tagged
+            || line
+                .speaker
+                .as_ref()
+                .is_some_and(|speaker| self.audio.has_voices(speaker));
+        if expected && self.missing_voices.insert(voice.clone()) {
+            println!("Warning: voice {} not found.", voice);
+        }
+        None
+    }
 
-        debug!("{:?}", self.line_buffer);
-        debug!("{:?}", self);
-        let line = self.line_buffer.remove(0);
-        self.speak_state = SpeakerState::parser_line_content(line);
+    /// 指令的目标角色：未指定时为当前说话的角色，角色未加载时打印警告
+    fn command_target(&self, name: &Option<String>) -> Option<String> {
+        let Some(name) = name.as_ref().or(self.speak_state.name.as_ref()) else {
+            println!("Warning: command has no target character.");
+            return None;
+        };
+        if self.character_manager.characters.contains_key(name) {
+            Some(name.clone())
+        } else {
+            println!("Warning: character {} not loaded.", name);
+            None
+        }
+    }
+
+    /// 重播回看中的语音
+    pub fn replay_voice(&mut self, voice: &str) {
+        self.audio.play_voice(voice);
+    }
+
+    /// 执行一条演出指令，引用了未加载的资源时打印警告并忽略
+    pub fn execute_command(&mut self, command: &StageCommand) {
+        match command {
+            StageCommand::Background {
+                name,
+                transition,
+                fit,
+                ken_burns,
+            } => {
+                if self.textures.as_ref().and_then(|t| t.get(name)).is_some() {
+                    let mut view = self.background_views.get(name).copied().unwrap_or_default();
+                    if let Some(fit) = fit {
+                        view.fit = *fit;
+                    }
+                    if ken_burns.is_some() {
+                        view.ken_burns = *ken_burns;
+                    }
+                    let from = self.background.replace(name.clone());
+                    let from_view = std::mem::replace(&mut self.background_view, view);
+                    if let Some(spec) = transition {
+                        self.transition = Some(Transition::new(*spec, from, from_view));
+                    }
+                } else {
+                    println!("Warning: background {} not loaded.", name);
+                }
+            }
+            StageCommand::Transition { spec } => {
+                self.transition = Some(Transition::new(
+                    *spec,
+                    self.background.clone(),
+                    self.background_view,
+                ));
+            }
+            StageCommand::Bgm { name, fade } => self.audio.play_bgm(name.as_deref(), *fade),
+            StageCommand::Sfx { name } => self.audio.play_sfx(name),
+            // 语音随台词一起处理，见 `update_display_text`
+            StageCommand::Voice { .. } => {}
+            StageCommand::Ambience { name, fade } => {
+                self.audio.play_ambience(name.as_deref(), *fade)
+            }
+            StageCommand::Show {
+                name,
+                slot,
+                animation,
+                enter,
+                duration,
+                easing,
+            } => {
+                if !self.character_manager.characters.contains_key(name) {
+                    println!("Warning: character {} not loaded.", name);
+                    return;
+                }
+                let stage = &mut self.character_manager;
+                match (slot, enter) {
+                    // 已在场：移动到新站位
+                    (Some(slot), _) if stage.is_on_stage(name) => {
+                        let default = MotionSpec::new(MotionKind::Move { from: 0.0 });
+                        stage.move_to(
+                            name,
+                            *slot,
+                            duration.unwrap_or(default.duration),
+                            easing.unwrap_or(default.easing),
+                        );
+                    }
+                    (slot, Some(kind)) if !stage.is_on_stage(name) => {
+                        let mut spec = MotionSpec::new(*kind);
+                        spec.duration = duration.unwrap_or(spec.duration);
+                        spec.easing = easing.unwrap_or(spec.easing);
+                        let slot = slot.unwrap_or_else(|| stage.free_slot());
+                        stage.enter(name, slot, spec);
+                    }
+                    (Some(slot), _) => stage.show(name, *slot),
+                    (None, _) => stage.show_auto(name),
+                }
+                if let Some(animation) = animation
+                    && let Err(e) = self.character_manager.set_animation(name, animation)
+                {
+                    println!("Warning: {}", e);
+                }
+            }
+            StageCommand::Hide { name, exit } => match exit {
+                Some(spec) => self.character_manager.exit(name, *spec),
+                None => self.character_manager.hide(name),
+            },
+            StageCommand::Track {
+                name,
+                animation,
+                track,
+                looping,
+                mix,
+                queue,
+            } => {
+                let Some(name) = self.command_target(name) else {
+                    return;
+                };
+                let Ok(character) = self.character_manager.character_mut(&name) else {
+                    return;
+                };
+                let result = match (animation, queue) {
+                    (None, _) => {
+                        character.clear_track(*track, *mix);
+                        Ok(())
+                    }
+                    (Some(animation), Some(delay)) => {
+                        character.queue_track(*track, animation, *looping, *delay, *mix)
+                    }
+                    (Some(animation), None) => {
+                        character.set_track(*track, animation, *looping, *mix)
+                    }
+                };
+                if let Err(e) = result {
+                    println!("Warning: {}", e);
+                }
+            }
+            StageCommand::Mix { name, duration } => {
+                if let Some(name) = self.command_target(name)
+                    && let Ok(character) = self.character_manager.character_mut(&name)
+                {
+                    character.set_mix(*duration);
+                }
+            }
+            StageCommand::Blink { name, enabled } => {
+                if let Some(name) = self.command_target(name)
+                    && let Ok(character) = self.character_manager.character_mut(&name)
+                {
+                    character.set_blinking(*enabled);
+                }
+            }
+            StageCommand::Skin { name, skin } => {
+                if let Some(name) = self.command_target(name)
+                    && let Ok(character) = self.character_manager.character_mut(&name)
+                    && let Err(e) = character.set_skin(skin)
+                {
+                    println!("Warning: {}", e);
+                }
+            }
+            StageCommand::Attachment {
+                name,
+                slot,
+                attachment,
+            } => {
+                if let Some(name) = self.command_target(name)
+                    && let Ok(character) = self.character_manager.character_mut(&name)
+                    && let Err(e) = character.set_attachment(slot, attachment.as_deref())
+                {
+                    println!("Warning: {}", e);
+                }
+            }
+            StageCommand::Highlight {
+                enabled,
+                narration_lit,
+            } => {
+                let highlight = &mut self.character_manager.highlight;
+                highlight.enabled = *enabled;
+                if let Some(narration_lit) = narration_lit {
+                    highlight.narration_lit = *narration_lit;
+                }
+            }
+            StageCommand::Motion { name, spec } => {
+                let Some(name) = name.as_ref().or(self.speak_state.name.as_ref()) else {
+                    println!("Warning: action has no target character.");
+                    return;
+                };
+                if self.character_manager.is_on_stage(name) {
+                    let name = name.clone();
+                    self.character_manager.play_motion(&name, *spec);
+                } else {
+                    println!("Warning: character {} is not on stage.", name);
+                }
+            }
+            StageCommand::Animation { name, animation } => {
+                let Some(name) = name.as_ref().or(self.speak_state.name.as_ref()) else {
+                    println!("Warning: animation {} has no target character.", animation);
+                    return;
+                };
+                if self.character_manager.characters.contains_key(name) {
+                    let name = name.clone();
+                    if let Err(e) = self.character_manager.set_animation(&name, animation) {
+                        println!("Warning: {}", e);
+                    }
+                } else {
+                    println!("Warning: character {} not loaded.", name);
+                }
+            }
+        }
     }
 
-    pub fn make_choice(&mut self, choice_index: usize) -> Result<()> {
-        if choice_index < self.current_choices.len() {
-            self.story.make_choice(choice_index)?;
-            self.current_choices.clear();
-            self.is_choosing = false;
-            self.advance_story()?;
+    pub fn make_choice(&mut self, choice_index: usize) -> StoryResult<()> {
+        if self.engine.has_events() {
+            return Ok(());
+        }
+        if let Some(text) = self.engine.choose(choice_index)? {
+            self.history.record_choice(&text);
+            self.audio.stop_voice();
+            self.process_events();
         }
         Ok(())
     }
@@ -180,48 +573,47 @@ impl<'a> GameState<'a> {
 
 pub struct SpeakerState {
     pub name: Option<String>,
+    /// 对话框中代替 `name` 显示的名字
+    pub display_name: Option<String>,
     pub animation: Option<String>,
     pub position: Option<Vec2>,
     pub color: Option<Color>,
     pub content: String,
+    /// 正在播放的语音名
+    pub voice: Option<String>,
+}
+
+impl Default for SpeakerState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SpeakerState {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             name: None,
+            display_name: None,
             animation: None,
             position: None,
             color: None,
             content: "".to_string(),
+            voice: None,
         }
     }
 
-    fn parser_line_content(line: inkling::Line) -> Self {
-        let mut speak_state = Self::new();
-        let line_content = line.text;
-        let regex = Regex::new(r"(.*?): ").unwrap();
-
-        let character = regex.find(line_content.as_bytes());
-
-        let mut speaker_string = String::new();
-        if let Some(speaker) = character {
-            speaker
-                .as_bytes()
-                .read_to_string(&mut speaker_string)
-                .unwrap();
-            speak_state.name = Some(speaker_string.replace(": ", "")).clone();
-            speak_state.content = line_content.replace(&speaker_string, "");
-        } else {
-            speak_state.content = line_content;
-        }
+    /// 对话框中显示的名字
+    pub fn shown_name(&self) -> Option<&String> {
+        self.display_name.as_ref().or(self.name.as_ref())
+    }
 
-        if !speak_state.content.is_empty() {
-            speak_state.content.pop();
-        }
-        if let Some(animation) = line.tags.get(0) {
-            speak_state.animation = Some(animation.clone());
+    fn from_line(line: DialogueLine) -> Self {
+        Self {
+            name: line.speaker,
+            display_name: line.display_name,
+            animation: line.animation,
+            content: line.content,
+            ..Self::new()
         }
-        speak_state
     }
 }
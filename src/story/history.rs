@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::story::SpeakerState;
+
+pub const HISTORY_CAPACITY: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub speaker: Option<String>,
+    /// 说话人的显示名，见 [`SpeakerState::display_name`]
+    #[serde(default)]
+    pub display_name: Option<String>,
+    pub content: String,
+    /// 玩家在此处选择的选项文本
+    pub choice: Option<String>,
+    /// 台词的语音，可在回看中重播
+    #[serde(default)]
+    pub voice: Option<String>,
+}
+
+/// 对话历史，超过容量时丢弃最早的记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct History {
+    entries: VecDeque<HistoryEntry>,
+    capacity: usize,
+    /// 回看窗口是否打开
+    #[serde(skip)]
+    pub is_open: bool,
+    /// 从最新一条向上滚动的条数
+    #[serde(skip)]
+    pub scroll: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            capacity,
+            is_open: false,
+            scroll: 0,
+        }
+    }
+
+    fn push(&mut self, entry: HistoryEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn record_line(&mut self, speak_state: &SpeakerState) {
+        self.push(HistoryEntry {
+            speaker: speak_state.name.clone(),
+            display_name: speak_state.display_name.clone(),
+            content: speak_state.content.clone(),
+            choice: None,
+            voice: speak_state.voice.clone(),
+        });
+    }
+
+    pub fn record_choice(&mut self, text: &str) {
+        self.push(HistoryEntry {
+            speaker: None,
+            display_name: None,
+            content: String::new(),
+            choice: Some(text.to_string()),
+            voice: None,
+        });
+    }
+
+    pub fn entries(&self) -> &VecDeque<HistoryEntry> {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn open(&mut self) {
+        self.is_open = true;
+        self.scroll = 0;
+    }
+
+    pub fn close(&mut self) {
+        self.is_open = false;
+        self.scroll = 0;
+    }
+
+    /// 向上滚动，`delta` 为正时查看更早的记录；滚回最新之后继续向下则关闭窗口
+    pub fn scroll_by(&mut self, delta: i32) {
+        if delta >= 0 {
+            self.scroll = (self.scroll + delta as usize).min(self.len().saturating_sub(1));
+        } else if self.scroll == 0 {
+            self.close();
+        } else {
+            self.scroll = self.scroll.saturating_sub(delta.unsigned_abs() as usize);
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(HISTORY_CAPACITY)
+    }
+}
@@ -1,8 +1,11 @@
 // 创建输入处理模块
 use macroquad::prelude::*;
 
-use crate::story::GameState;
-use anyhow::Result;
+use crate::story::ui::{backlog_layout, error_overlay_layout, save_menu_layout};
+use crate::story::{
+    ChoiceMenu, GameState, PlayMode, SaveMenuMode, StoryResult, VIRTUAL_HEIGHT, VIRTUAL_WIDTH,
+    canvas_mouse_position,
+};
 
 pub struct InputHandler {
     debounce_time: f32,
@@ -35,59 +38,193 @@ impl InputHandler {
     }
 }
 
-pub fn handle_input(state: &mut GameState) -> Result<()> {
-    // 空格键推进故事（当不在选择状态时）
-    if is_key_pressed(KeyCode::Space) && !state.is_choosing && state.should_continue {
-        state.should_continue = false;
-        state.advance_story()?;
-    }
-
-    // 鼠标点击继续（当不在选择状态时）
-    if is_mouse_button_pressed(MouseButton::Left) && !state.is_choosing && state.should_continue {
-        state.should_continue = false;
-        state.advance_story()?;
-    }
-
-    // 处理选择（数字键1-9）
-    if state.is_choosing {
-        for i in 0..state.current_choices.len().min(9) {
-            let key_code = match i {
-                0 => KeyCode::Key1,
-                1 => KeyCode::Key2,
-                2 => KeyCode::Key3,
-                3 => KeyCode::Key4,
-                4 => KeyCode::Key5,
-                5 => KeyCode::Key6,
-                6 => KeyCode::Key7,
-                7 => KeyCode::Key8,
-                8 => KeyCode::Key9,
-                _ => continue,
-            };
-
-            if is_key_pressed(key_code) {
-                state.make_choice(i)?;
-                break;
-            }
+pub fn handle_input(state: &mut GameState) -> StoryResult<()> {
+    // 错误提示：按 Esc 或点击提示框关闭，显示期间的输入都不会传给剧情
+    if state.errors.visible {
+        let (rect, _) = error_overlay_layout(&state.errors, state.font.as_ref());
+        if is_key_pressed(KeyCode::Escape)
+            || (is_mouse_button_pressed(MouseButton::Left)
+                && rect.contains(canvas_mouse_position()))
+        {
+            state.errors.dismiss();
+        }
+        state.playback.skip_held = false;
+        return Ok(());
+    }
+
+    // 存档菜单：F5 存档，F9 读档，打开时屏蔽其他输入
+    if state.save_menu.is_open() {
+        return handle_save_menu(state);
+    }
+    if is_key_pressed(KeyCode::F5) {
+        state
+            .save_menu
+            .open(SaveMenuMode::Save, &state.save_manager);
+        return Ok(());
+    }
+    if is_key_pressed(KeyCode::F9) {
+        state
+            .save_menu
+            .open(SaveMenuMode::Load, &state.save_manager);
+        return Ok(());
+    }
+
+    // 转场期间屏蔽其他输入，点击或空格直接结束转场
+    if let Some(transition) = &mut state.transition {
+        if is_mouse_button_pressed(MouseButton::Left) || is_key_pressed(KeyCode::Space) {
+            transition.finish();
+        }
+        return Ok(());
+    }
+
+    // M 键暂停/恢复全部声音
+    if is_key_pressed(KeyCode::M) {
+        if state.audio.is_paused() {
+            state.audio.resume();
+        } else {
+            state.audio.pause();
         }
+    }
 
-        // 鼠标点击选择
+    // 回看窗口：滚轮向上或 L 键打开，打开时屏蔽其他输入
+    let (_, wheel_y) = mouse_wheel();
+    if state.history.is_open {
+        if wheel_y > 0.0 || is_key_pressed(KeyCode::Up) {
+            state.history.scroll_by(1);
+        } else if wheel_y < 0.0 || is_key_pressed(KeyCode::Down) {
+            state.history.scroll_by(-1);
+        }
+        // 点击重播按钮播放该句语音
         if is_mouse_button_pressed(MouseButton::Left) {
-            let (mouse_x, mouse_y) = mouse_position();
+            let mouse = canvas_mouse_position();
+            let voice = backlog_layout(&state.history, state.font.as_ref())
+                .into_iter()
+                .find(|block| block.voice_button.is_some_and(|b| b.contains(mouse)))
+                .and_then(|block| state.history.entries()[block.entry].voice.clone());
+            if let Some(voice) = voice {
+                state.replay_voice(&voice);
+            }
+        }
+        if is_key_pressed(KeyCode::L)
+            || is_key_pressed(KeyCode::Escape)
+            || is_mouse_button_pressed(MouseButton::Right)
+        {
+            state.history.close();
+        }
+        return Ok(());
+    } else if (wheel_y > 0.0 || is_key_pressed(KeyCode::L)) && !state.history.is_empty() {
+        state.history.open();
+        return Ok(());
+    }
 
-            for (i, choice) in state.current_choices.iter().enumerate() {
-                println!("{:?}", choice);
+    // A 切换自动播放，S 切换快进，按住 Ctrl 临时快进
+    if is_key_pressed(KeyCode::A) {
+        state.playback.toggle(PlayMode::Auto);
+    }
+    if is_key_pressed(KeyCode::S) {
+        state.playback.toggle(PlayMode::Skip);
+    }
+    state.playback.skip_held =
+        is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
 
-                let y_pos = screen_height() / 2.0 - (i as f32 * 84.0) - 34.;
+    // 空格键或鼠标点击：补全当前台词或推进故事（当不在选择状态时）
+    let advance = is_key_pressed(KeyCode::Space) || is_mouse_button_pressed(MouseButton::Left);
+    if advance && !state.engine.is_choosing && state.should_continue {
+        if state.playback.mode == PlayMode::Skip {
+            state.playback.stop();
+        }
+        state.playback.reset_timer();
+        state.advance_or_complete()?;
+    }
+
+    // 处理选择：出场动画结束后才接受输入
+    let count = state.engine.current_choices.len();
+    if state.engine.is_choosing && state.choice_menu.is_ready(count) {
+        // 数字键1-9
+        if let Some(i) = pressed_number(count) {
+            state.make_choice(i)?;
+            return Ok(());
+        }
 
-                // 简单的点击区域检测
-                if mouse_y >= y_pos && mouse_y < y_pos + 30.0 {
-                    if mouse_x >= 40.0 && mouse_x < screen_width() - 40. {
-                        state.make_choice(i)?;
-                        break;
-                    }
-                }
+        // 方向键移动焦点，回车或空格确认
+        if is_key_pressed(KeyCode::Up) {
+            state.choice_menu.move_focus(-1, count);
+        }
+        if is_key_pressed(KeyCode::Down) {
+            state.choice_menu.move_focus(1, count);
+        }
+        if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::Space) {
+            if let Some(focused) = state.choice_menu.focused {
+                state.make_choice(focused)?;
+                return Ok(());
+            }
+        }
+
+        // 鼠标：与绘制使用同一份布局，按下与松开在同一个选项上才算选择
+        let rects = ChoiceMenu::layout(count, VIRTUAL_WIDTH, VIRTUAL_HEIGHT);
+        let hovered = ChoiceMenu::hit_test(&rects, canvas_mouse_position());
+        if hovered.is_some() && mouse_delta_position() != Vec2::ZERO {
+            state.choice_menu.focused = hovered;
+        }
+        if is_mouse_button_pressed(MouseButton::Left) {
+            state.choice_menu.pressed = hovered;
+        }
+        if is_mouse_button_released(MouseButton::Left) {
+            let pressed = state.choice_menu.pressed.take();
+            if let Some(i) = pressed.filter(|&i| hovered == Some(i)) {
+                state.make_choice(i)?;
             }
         }
     }
     Ok(())
 }
+
+/// 存档菜单：数字键、回车或点击选择槽位，Esc、右键或再按 F5/F9 关闭
+fn handle_save_menu(state: &mut GameState) -> StoryResult<()> {
+    if is_key_pressed(KeyCode::Escape)
+        || is_key_pressed(KeyCode::F5)
+        || is_key_pressed(KeyCode::F9)
+        || is_mouse_button_pressed(MouseButton::Right)
+    {
+        state.save_menu.close();
+        return Ok(());
+    }
+    if is_key_pressed(KeyCode::Up) {
+        state.save_menu.move_focus(-1);
+    }
+    if is_key_pressed(KeyCode::Down) {
+        state.save_menu.move_focus(1);
+    }
+    let rects = save_menu_layout(state.save_menu.slots.len());
+    let hovered = rects
+        .iter()
+        .position(|rect| rect.contains(canvas_mouse_position()));
+    if let Some(slot) = hovered
+        && mouse_delta_position() != Vec2::ZERO
+    {
+        state.save_menu.focused = slot;
+    }
+    let chosen = pressed_number(state.save_menu.slots.len())
+        .or_else(|| is_key_pressed(KeyCode::Enter).then_some(state.save_menu.focused))
+        .or_else(|| hovered.filter(|_| is_mouse_button_pressed(MouseButton::Left)));
+    match chosen {
+        Some(slot) => state.choose_save_slot(slot),
+        None => Ok(()),
+    }
+}
+
+/// 本帧按下的数字键 1-9 对应的下标，只考虑前 `count` 个
+fn pressed_number(count: usize) -> Option<usize> {
+    const KEYS: [KeyCode; 9] = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+        KeyCode::Key8,
+        KeyCode::Key9,
+    ];
+    KEYS.iter().take(count).position(|key| is_key_pressed(*key))
+}
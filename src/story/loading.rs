@@ -0,0 +1,45 @@
+use macroquad::prelude::*;
+
+/// 资源载入进度，由 [`draw_loading_screen`](crate::story::ui::draw_loading_screen) 画出
+#[derive(Debug, Clone, Default)]
+pub struct LoadProgress {
+    /// 预计载入的资源数
+    pub total: usize,
+    pub loaded: usize,
+    pub failed: usize,
+    /// 正在载入的资源名
+    pub current: String,
+    /// 字体载入后用于绘制载入画面
+    pub font: Option<Font>,
+}
+
+impl LoadProgress {
+    pub fn new(total: usize) -> Self {
+        Self {
+            total,
+            ..Self::default()
+        }
+    }
+
+    /// 已处理的比例 (0.0 - 1.0)，失败的资源也计入
+    pub fn ratio(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            ((self.loaded + self.failed) as f32 / self.total as f32).min(1.0)
+        }
+    }
+
+    /// 记录接下来要载入的资源名
+    pub fn begin(&mut self, name: &str) {
+        self.current = name.to_string();
+    }
+
+    /// 记录上一项资源是否载入成功
+    pub fn finish<T, E>(&mut self, result: &Result<T, E>) {
+        match result {
+            Ok(_) => self.loaded += 1,
+            Err(_) => self.failed += 1,
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::spine::DEFAULT_SPINE_SCALE;
+use crate::story::character::{Blink, HighlightSettings, LipSync};
+use crate::story::{
+    BackgroundFit, BackgroundView, DEFAULT_TEXT_SPEED, KenBurns, StoryError, StoryResult,
+    StreamingSettings, VolumeSettings,
+};
+
+/// 覆盖清单中 `root` 的环境变量
+pub const ASSET_ROOT_ENV: &str = "BLUEARCHIVE_ASSET_ROOT";
+
+/// 资源清单，描述游戏需要加载的全部资源
+/// 清单中的路径均相对于 `root` 解析（绝对路径保持不变）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default = "default_root")]
+    pub root: PathBuf,
+    pub story: String,
+    pub font: String,
+    pub atlas: AtlasEntry,
+    /// 存档目录，不随 `root` 解析
+    #[serde(default = "default_save_dir")]
+    pub save_dir: PathBuf,
+    /// 逐字显示速度（字/秒）
+    #[serde(default = "default_text_speed")]
+    pub text_speed: f32,
+    /// 快进时是否跳过未读台词，为 `false` 时遇到未读台词停下
+    #[serde(default)]
+    pub skip_unread: bool,
+    #[serde(default)]
+    pub default_background: Option<String>,
+    #[serde(default)]
+    pub default_bgm: Option<String>,
+    /// 未单独指定时背景的缩放方式
+    #[serde(default)]
+    pub background_fit: BackgroundFit,
+    #[serde(default)]
+    pub backgrounds: HashMap<String, BackgroundEntry>,
+    #[serde(default)]
+    pub textures: HashMap<String, String>,
+    #[serde(default)]
+    pub bgm: HashMap<String, String>,
+    #[serde(default)]
+    pub sfx: HashMap<String, String>,
+    #[serde(default)]
+    pub ambience: HashMap<String, String>,
+    /// 语音目录，其下按角色名分子目录
+    #[serde(default = "default_voice_dir")]
+    pub voice_dir: String,
+    /// 各声道的初始音量
+    #[serde(default)]
+    pub volume: VolumeSettings,
+    /// 突出说话角色的设置
+    #[serde(default)]
+    pub highlight: HighlightSettings,
+    /// 显示名 -> Spine 资源
+    #[serde(default)]
+    pub characters: HashMap<String, CharacterEntry>,
+    /// 按 knot 分段载入背景、角色与音乐
+    #[serde(default)]
+    pub streaming: StreamingSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AtlasEntry {
+    pub texture: String,
+    pub yaml: String,
+}
+
+/// 背景可以只写路径，也可以附带缩放方式和镜头动画
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BackgroundEntry {
+    Path(String),
+    Detailed {
+        path: String,
+        #[serde(default)]
+        fit: Option<BackgroundFit>,
+        #[serde(default)]
+        ken_burns: Option<KenBurns>,
+    },
+}
+
+impl BackgroundEntry {
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Path(path) | Self::Detailed { path, .. } => path,
+        }
+    }
+
+    /// 该背景的默认显示方式，`fit` 未指定时使用 `default_fit`
+    pub fn view(&self, default_fit: BackgroundFit) -> BackgroundView {
+        match self {
+            Self::Path(_) => BackgroundView::new(default_fit, None),
+            Self::Detailed { fit, ken_burns, .. } => {
+                BackgroundView::new(fit.unwrap_or(default_fit), *ken_burns)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CharacterEntry {
+    pub atlas: String,
+    /// `.skel` 按二进制骨骼读取，`.json` 按 JSON 骨骼读取
+    pub skel: String,
+    pub texture: String,
+    /// 骨骼坐标到画布像素的缩放
+    #[serde(default = "default_spine_scale")]
+    pub scale: f32,
+    /// 相对站位锚点的偏移（画布像素），向右、向下为正
+    #[serde(default)]
+    pub offset: (f32, f32),
+    /// 0 号轨道的初始动画
+    #[serde(default = "default_animation")]
+    pub animation: String,
+    /// 1 号轨道循环播放的待机动画，设为 `null` 时不播放
+    #[serde(default = "default_idle")]
+    pub idle: Option<String>,
+    /// 说话时的嘴型动画
+    #[serde(default)]
+    pub lip_sync: Option<LipSync>,
+    /// 自动眨眼
+    #[serde(default)]
+    pub blink: Option<Blink>,
+}
+
+fn default_root() -> PathBuf {
+    PathBuf::from("assets")
+}
+
+fn default_spine_scale() -> f32 {
+    DEFAULT_SPINE_SCALE
+}
+
+fn default_animation() -> String {
+    "00".to_string()
+}
+
+fn default_idle() -> Option<String> {
+    Some("Idle_01".to_string())
+}
+
+fn default_voice_dir() -> String {
+    "voice".to_string()
+}
+
+fn default_text_speed() -> f32 {
+    DEFAULT_TEXT_SPEED
+}
+
+fn default_save_dir() -> PathBuf {
+    PathBuf::from("saves")
+}
+
+impl Manifest {
+    /// 读取清单文件，若设置了 `BLUEARCHIVE_ASSET_ROOT` 则以其替换 `root`
+    pub fn load(path: &str) -> StoryResult<Self> {
+        let content = fs::read_to_string(path).map_err(|e| StoryError::io(path, e))?;
+        let mut manifest: Manifest =
+            serde_yaml::from_str(&content).map_err(|e| StoryError::Parse {
+                path: path.to_string(),
+                message: e.to_string(),
+            })?;
+        if let Ok(root) = std::env::var(ASSET_ROOT_ENV) {
+            manifest.root = PathBuf::from(root);
+        }
+        Ok(manifest)
+    }
+
+    /// 将清单中的相对路径解析为可直接加载的路径
+    pub fn resolve(&self, path: &str) -> String {
+        let path = Path::new(path);
+        if path.is_absolute() {
+            path.to_string_lossy().to_string()
+        } else {
+            self.root.join(path).to_string_lossy().to_string()
+        }
+    }
+
+    pub fn character(&self, name: &str) -> Option<&CharacterEntry> {
+        self.characters.get(name)
+    }
+}
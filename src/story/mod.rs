@@ -1,12 +1,46 @@
 // pub mod assets_manager;
+mod audio;
+mod background;
+mod canvas;
 mod character;
+mod choice;
+mod command;
+mod dialogue;
+mod engine;
+mod error;
 mod game_state;
+mod history;
 pub mod input;
+mod loading;
+mod manifest;
+mod motion;
+mod playback;
 mod resource;
+mod reveal;
+mod save;
+mod scene;
 pub mod sprite;
 mod texture_manager;
+mod transition;
 pub mod ui;
 
+pub use audio::*;
+pub use background::*;
+pub use canvas::*;
+pub use choice::*;
+pub use command::*;
+pub use dialogue::*;
+pub use engine::*;
+pub use error::*;
 pub use game_state::*;
+pub use history::*;
+pub use loading::*;
+pub use manifest::*;
+pub use motion::*;
+pub use playback::*;
 pub use resource::*;
+pub use reveal::*;
+pub use save::*;
+pub use scene::*;
 pub use texture_manager::*;
+pub use transition::*;
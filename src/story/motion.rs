@@ -0,0 +1,221 @@
+use macroquad::prelude::*;
+use std::f32::consts::PI;
+
+/// 缓动曲线
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    In,
+    Out,
+    InOut,
+    /// 略微冲过终点再回弹
+    Back,
+}
+
+impl Easing {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Self::Linear),
+            "in" => Some(Self::In),
+            "out" => Some(Self::Out),
+            "inout" | "in-out" => Some(Self::InOut),
+            "back" => Some(Self::Back),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::In => t * t,
+            Self::Out => 1.0 - (1.0 - t) * (1.0 - t),
+            Self::InOut => t * t * (3.0 - 2.0 * t),
+            Self::Back => {
+                let c = 1.70158;
+                let t = t - 1.0;
+                1.0 + (c + 1.0) * t * t * t + c * t * t
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MotionKind {
+    /// 淡入淡出
+    Fade,
+    /// 从左侧滑入 / 向左侧滑出
+    SlideLeft,
+    SlideRight,
+    /// 原地轻跳
+    Hop,
+    /// 左右抖动
+    Shake,
+    /// 缩放到 `scale` 倍特写，`from` 为开始时的倍数
+    CloseUp {
+        from: f32,
+        scale: f32,
+    },
+    /// 从站位比例 `from` 平滑移到当前站位
+    Move {
+        from: f32,
+    },
+}
+
+impl MotionKind {
+    /// 解析入场/退场方式
+    pub fn parse_transition(name: &str) -> Option<Self> {
+        match name {
+            "fade" => Some(Self::Fade),
+            "slide-left" | "left" => Some(Self::SlideLeft),
+            "slide-right" | "right" => Some(Self::SlideRight),
+            _ => None,
+        }
+    }
+
+    pub fn default_duration(&self) -> f32 {
+        match self {
+            Self::Fade | Self::SlideLeft | Self::SlideRight => 0.4,
+            Self::Hop => 0.35,
+            Self::Shake => 0.5,
+            Self::CloseUp { .. } | Self::Move { .. } => 0.5,
+        }
+    }
+
+    pub fn default_easing(&self) -> Easing {
+        match self {
+            Self::Hop | Self::Shake => Easing::Linear,
+            Self::CloseUp { .. } | Self::Move { .. } => Easing::InOut,
+            Self::Fade | Self::SlideLeft | Self::SlideRight => Easing::Out,
+        }
+    }
+}
+
+/// 脚本给出的动作参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionSpec {
+    pub kind: MotionKind,
+    pub duration: f32,
+    pub easing: Easing,
+}
+
+impl MotionSpec {
+    pub fn new(kind: MotionKind) -> Self {
+        Self {
+            kind,
+            duration: kind.default_duration(),
+            easing: kind.default_easing(),
+        }
+    }
+}
+
+/// 动作对角色位置、缩放、透明度和颜色的影响，多个动作叠加时位移相加、其余相乘
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pose {
+    pub offset: Vec2,
+    pub scale: f32,
+    pub alpha: f32,
+    pub tint: Color,
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            scale: 1.0,
+            alpha: 1.0,
+            tint: WHITE,
+        }
+    }
+}
+
+impl Pose {
+    pub fn combine(self, other: Pose) -> Pose {
+        Pose {
+            offset: self.offset + other.offset,
+            scale: self.scale * other.scale,
+            alpha: self.alpha * other.alpha,
+            tint: Color::new(
+                self.tint.r * other.tint.r,
+                self.tint.g * other.tint.g,
+                self.tint.b * other.tint.b,
+                self.tint.a * other.tint.a,
+            ),
+        }
+    }
+}
+
+/// 正在播放的动作，`exiting` 的动作结束后角色离场
+#[derive(Debug, Clone)]
+pub struct Motion {
+    pub spec: MotionSpec,
+    pub exiting: bool,
+    pub elapsed: f32,
+}
+
+impl Motion {
+    pub fn new(spec: MotionSpec, exiting: bool) -> Self {
+        Self {
+            spec,
+            exiting,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.elapsed += delta_time;
+    }
+
+    fn progress(&self) -> f32 {
+        if self.spec.duration <= 0.0 {
+            1.0
+        } else {
+            (self.elapsed / self.spec.duration).clamp(0.0, 1.0)
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress() >= 1.0
+    }
+
+    /// `slot_ratio` 为角色当前站位，`width`/`height` 为画布尺寸
+    pub fn pose(&self, slot_ratio: f32, width: f32, height: f32) -> Pose {
+        let t = self.spec.easing.apply(self.progress());
+        // 入场时从 0 走到 1，退场时反过来
+        let shown = if self.exiting { 1.0 - t } else { t };
+        let slide = width * 0.3;
+        match self.spec.kind {
+            MotionKind::Fade => Pose {
+                alpha: shown,
+                ..Pose::default()
+            },
+            MotionKind::SlideLeft => Pose {
+                offset: vec2(-(1.0 - shown) * slide, 0.0),
+                alpha: shown,
+                ..Pose::default()
+            },
+            MotionKind::SlideRight => Pose {
+                offset: vec2((1.0 - shown) * slide, 0.0),
+                alpha: shown,
+                ..Pose::default()
+            },
+            MotionKind::Hop => Pose {
+                offset: vec2(0.0, -(t * PI).sin() * height * 0.04),
+                ..Pose::default()
+            },
+            MotionKind::Shake => Pose {
+                offset: vec2((t * PI * 6.0).sin() * (1.0 - t) * width * 0.01, 0.0),
+                ..Pose::default()
+            },
+            // 站位上记录的是最终倍数，这里只补上尚未走完的部分
+            MotionKind::CloseUp { from, scale } => Pose {
+                scale: (from + (scale - from) * t) / scale,
+                ..Pose::default()
+            },
+            MotionKind::Move { from } => Pose {
+                offset: vec2((from - slot_ratio) * width * (1.0 - t), 0.0),
+                ..Pose::default()
+            },
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::story::{StoryError, StoryResult};
+
+/// 已读记录在存档目录下的文件名
+pub const READ_LINES_FILE: &str = "read_lines.yaml";
+
+/// 新增的已读记录最多隔多少秒写回文件
+pub const READ_LINES_FLUSH_INTERVAL: f32 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayMode {
+    Normal,
+    /// 台词显示完后等待一段时间自动推进
+    Auto,
+    /// 快速跳过台词
+    Skip,
+}
+
+/// 自动播放与快进的设置和计时
+#[derive(Debug, Clone)]
+pub struct Playback {
+    pub mode: PlayMode,
+    /// 按住 Ctrl 时临时快进
+    pub skip_held: bool,
+    /// 自动模式下每句的基础等待秒数
+    pub auto_base_delay: f32,
+    /// 自动模式下每个字额外等待的秒数
+    pub auto_char_delay: f32,
+    /// 快进时每句停留的秒数
+    pub skip_interval: f32,
+    /// 为 `false` 时快进遇到未读台词会停下
+    pub skip_unread: bool,
+    timer: f32,
+}
+
+impl Playback {
+    pub fn new() -> Self {
+        Self {
+            mode: PlayMode::Normal,
+            skip_held: false,
+            auto_base_delay: 1.0,
+            auto_char_delay: 0.08,
+            skip_interval: 0.05,
+            skip_unread: false,
+            timer: 0.0,
+        }
+    }
+
+    pub fn is_skipping(&self) -> bool {
+        self.mode == PlayMode::Skip || self.skip_held
+    }
+
+    pub fn toggle(&mut self, mode: PlayMode) {
+        self.mode = if self.mode == mode {
+            PlayMode::Normal
+        } else {
+            mode
+        };
+        self.timer = 0.0;
+    }
+
+    pub fn stop(&mut self) {
+        self.mode = PlayMode::Normal;
+        self.skip_held = false;
+        self.timer = 0.0;
+    }
+
+    pub fn reset_timer(&mut self) {
+        self.timer = 0.0;
+    }
+
+    pub fn auto_delay(&self, content: &str) -> f32 {
+        self.auto_base_delay + self.auto_char_delay * content.chars().count() as f32
+    }
+
+    /// 累计时间，超过 `delay` 时返回 `true` 并重新计时
+    pub fn tick(&mut self, delta_time: f32, delay: f32) -> bool {
+        self.timer += delta_time;
+        if self.timer >= delay {
+            self.timer = 0.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 已读台词记录，以 knot/行序号（附带文本散列）为键，跨存档持久保存；
+/// 新记录先留在内存中，定时、存档或退出时再写回文件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReadTracker {
+    lines: HashSet<String>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// 有尚未写回的记录
+    #[serde(skip)]
+    dirty: bool,
+    /// 距上次写回的秒数
+    #[serde(skip)]
+    elapsed: f32,
+}
+
+impl ReadTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 从文件读取已读记录，文件不存在时从空记录开始
+    pub fn load(path: impl Into<PathBuf>) -> StoryResult<Self> {
+        let path = path.into();
+        let name = path.to_string_lossy().to_string();
+        let mut tracker = if path.exists() {
+            let content = fs::read_to_string(&path).map_err(|e| StoryError::io(&name, e))?;
+            serde_yaml::from_str(&content).map_err(|e| StoryError::Parse {
+                path: name,
+                message: e.to_string(),
+            })?
+        } else {
+            Self::new()
+        };
+        tracker.path = Some(path);
+        Ok(tracker)
+    }
+
+    /// `index` 为台词在所在 knot 中的位置，与从哪个分支到达无关
+    pub fn key(knot: &str, index: usize, text: &str) -> String {
+        // FNV-1a，保证不同版本编译出的散列一致
+        let mut hash: u32 = 0x811c9dc5;
+        for byte in text.as_bytes() {
+            hash ^= *byte as u32;
+            hash = hash.wrapping_mul(0x01000193);
+        }
+        format!("{}:{}:{:08x}", knot, index, hash)
+    }
+
+    pub fn is_read(&self, key: &str) -> bool {
+        self.lines.contains(key)
+    }
+
+    /// 标记为已读，返回此前是否已读
+    pub fn mark_read(&mut self, key: String) -> bool {
+        if self.lines.contains(&key) {
+            return true;
+        }
+        self.lines.insert(key);
+        self.dirty = true;
+        false
+    }
+
+    /// 每帧调用，有新记录且距上次写回超过 [`READ_LINES_FLUSH_INTERVAL`] 时写回文件
+    pub fn update(&mut self, delta_time: f32) {
+        self.elapsed += delta_time;
+        if self.elapsed >= READ_LINES_FLUSH_INTERVAL {
+            self.flush();
+        }
+    }
+
+    /// 把新增的记录写回文件，没有新记录时什么也不做
+    pub fn flush(&mut self) {
+        self.elapsed = 0.0;
+        if !self.dirty {
+            return;
+        }
+        let Some(path) = &self.path else {
+            return;
+        };
+        match self.write(path) {
+            Ok(()) => self.dirty = false,
+            Err(e) => println!("Warning: can not write read lines. {}", e),
+        }
+    }
+
+    fn write(&self, path: &Path) -> StoryResult<()> {
+        let name = path.to_string_lossy();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| StoryError::io(&name, e))?;
+        }
+        let content = serde_yaml::to_string(self).map_err(|e| StoryError::Parse {
+            path: name.to_string(),
+            message: e.to_string(),
+        })?;
+        fs::write(path, content).map_err(|e| StoryError::io(&name, e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggling_switches_between_modes() {
+        let mut playback = Playback::new();
+        playback.toggle(PlayMode::Auto);
+        assert_eq!(playback.mode, PlayMode::Auto);
+        assert!(!playback.is_skipping());
+        playback.toggle(PlayMode::Skip);
+        assert_eq!(playback.mode, PlayMode::Skip);
+        assert!(playback.is_skipping());
+        playback.toggle(PlayMode::Skip);
+        assert_eq!(playback.mode, PlayMode::Normal);
+    }
+
+    #[test]
+    fn holding_ctrl_skips_until_stopped() {
+        let mut playback = Playback::new();
+        playback.skip_held = true;
+        assert!(playback.is_skipping());
+        playback.toggle(PlayMode::Auto);
+        playback.stop();
+        assert_eq!(playback.mode, PlayMode::Normal);
+        assert!(!playback.is_skipping());
+    }
+
+    #[test]
+    fn tick_fires_after_delay_and_restarts() {
+        let mut playback = Playback::new();
+        let delay = playback.auto_delay("你好");
+        assert_eq!(
+            delay,
+            playback.auto_base_delay + 2.0 * playback.auto_char_delay
+        );
+        assert!(!playback.tick(delay / 2.0, delay));
+        assert!(playback.tick(delay / 2.0, delay));
+        assert!(!playback.tick(delay / 2.0, delay));
+        playback.toggle(PlayMode::Auto);
+        assert!(!playback.tick(delay / 2.0, delay));
+    }
+
+    #[test]
+    fn keys_combine_knot_index_and_text() {
+        let key = ReadTracker::key("end", 3, "阿罗娜: 当然。");
+        assert!(key.starts_with("end:3:"));
+        assert_eq!(key.len(), "end:3:".len() + 8);
+        assert_eq!(key, ReadTracker::key("end", 3, "阿罗娜: 当然。"));
+        assert_ne!(key, ReadTracker::key("end", 4, "阿罗娜: 当然。"));
+        assert_ne!(key, ReadTracker::key("end", 3, "阿罗娜: 好的。"));
+    }
+
+    #[test]
+    fn read_lines_are_written_in_batches() {
+        let path = std::env::temp_dir().join(format!("read_lines_{}.yaml", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut tracker = ReadTracker::load(&path).unwrap();
+        assert!(!tracker.mark_read("end:0:00000000".to_string()));
+        assert!(tracker.mark_read("end:0:00000000".to_string()));
+        tracker.update(READ_LINES_FLUSH_INTERVAL / 2.0);
+        assert!(!path.exists());
+        tracker.update(READ_LINES_FLUSH_INTERVAL / 2.0);
+        assert!(ReadTracker::load(&path).unwrap().is_read("end:0:00000000"));
+
+        tracker.mark_read("end:1:00000000".to_string());
+        tracker.flush();
+        let reloaded = ReadTracker::load(&path).unwrap();
+        assert_eq!(reloaded.len(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+}
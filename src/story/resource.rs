@@ -1,120 +1,481 @@
-use std::{collections::HashMap, io::Read, panic};
-
-use macroquad::{audio::load_sound, prelude::*};
+use macroquad::prelude::*;
+use std::collections::{HashSet, VecDeque};
 
 use crate::{
     spine::{SkeletonPath, SpineDemo},
-    story::{GameState, TextureManager, character::CharacterManager, sprite::SpriteAtlas},
+    story::{
+        AudioManager, CharacterEntry, GameState, LoadProgress, Manifest, READ_LINES_FILE,
+        ROOT_KNOT, ReadTracker, SAVE_SLOTS, SaveManager, SceneAsset, SceneStreamer, StoryError,
+        StoryResult, TextureManager, character::CharacterManager, sprite::SpriteAtlas,
+    },
 };
-use anyhow::Result;
-use std::fs::File;
-
-fn get_characters(inkling_path: &str) -> Vec<String> {
-    let mut characters_name = Vec::new();
-    let mut f = File::open(inkling_path).expect(&format!("Can not found {}.", inkling_path));
-    let mut content = String::new();
-    f.read_to_string(&mut content)
-        .unwrap_or_else(|e| panic!("Can not read file {}. {:?}", inkling_path, e));
-    for i in content.lines() {
-        let line: Vec<&str> = i.split(":").collect();
-        if line.len() == 2 {
-            if let Some(character) = line.get(0) {
-                match characters_name.iter().find(|&x| x == character) {
-                    None => characters_name.push(character.to_string()),
-                    _ => (),
-                }
-            }
-        }
-    }
-    characters_name
-}
+
+/// 读档后补放音乐的淡入时间（秒）
+const STREAM_FADE: f32 = 0.5;
 
 async fn load_characters(
     character_manager: &mut CharacterManager,
+    manifest: &Manifest,
     character_name: &str,
-    character_path: &str,
-) {
+    entry: &CharacterEntry,
+) -> StoryResult<()> {
     println!("Start load character {}.", character_name);
-    let path = format!(
-        "/home/kina/temp/code/rust/spine/data/{}/{}",
-        character_path, character_path
-    );
-    let texture_path = format!("{}_spr.png", path);
+    let texture_path = manifest.resolve(&entry.texture);
 
     let texture = load_texture(&texture_path)
         .await
-        .unwrap_or_else(|e| panic!("Can not load character texture. {:?}", e));
-    let atlas_path = format!("{}_spr.atlas", path);
-    let skel_path = format!("{}_spr.skel", path);
+        .map_err(|e| StoryError::Texture {
+            path: texture_path.clone(),
+            message: format!("{:?}", e),
+        })?;
+    let atlas_path = manifest.resolve(&entry.atlas);
+    let skel_path = manifest.resolve(&entry.skel);
 
     let spine_demo = SpineDemo::new(
         atlas_path,
-        SkeletonPath::Binary(skel_path),
-        "00".to_string(),
+        SkeletonPath::detect(skel_path),
+        entry.animation.clone(),
     );
+    let spine_demo = match &entry.idle {
+        Some(idle) => spine_demo.with_track(1, idle),
+        None => spine_demo.without_tracks(),
+    };
     character_manager
         .register(character_name.to_string(), texture, spine_demo)
-        .await
-        .unwrap_or_else(|e| {
-            panic!(
-                "Can not load character {} which local in {}. {:?}",
-                character_name, character_path, e
-            )
-        });
+        .await?;
+    if let Some(character) = character_manager.characters.get_mut(character_name) {
+        character.set_scale(entry.scale);
+        character.offset = vec2(entry.offset.0, entry.offset.1);
+        character.lip_sync = entry.lip_sync.clone();
+        character.blink = entry.blink.clone();
+        character.set_blinking(entry.blink.as_ref().is_some_and(|b| b.enabled));
+    }
     println!("Load character {} texture success.", character_name);
+    Ok(())
 }
 
-pub async fn load_resource<'a>() -> Result<GameState<'a>> {
-    let mut state = GameState::new()?;
-    let mut textures = TextureManager::new();
-    println!("Resource load start.");
-
-    let font = load_ttf_font("assets/NotoSansSC-Medium.otf").await.unwrap();
-    println!("Load font success.");
-    textures
-        .load_texture_auto(
-            "/home/kina/temp/code/rust/spine/01_Background/BG_MainOffice_Night.png",
-            Some("background"),
-        )
-        .await
-        .expect("background loaded error");
-    println!("load backgrund success.");
-    // let sound = load_sound("assets/Track_64_Mitsukiyo_Pixel_time.ogg")
-    //     .await
-    //     .unwrap_or_else(|_| panic!("load sound failed"));
-    println!("Load sound success.");
-    let mut characters_path = HashMap::new();
-    characters_path.insert(String::from("圣园未花"), String::from("NP0100"));
-    characters_path.insert(String::from("亚子"), String::from("ako"));
-    characters_path.insert(String::from("阿罗娜"), String::from("arona"));
-    characters_path.insert(String::from("普拉娜"), String::from("NP0035"));
-    characters_path.insert(String::from("早濑优香"), String::from("yuuka"));
-    characters_path.insert(String::from("黑见芹香"), String::from("serika"));
-    let characters = get_characters("assets/story.ink");
-    for name in characters {
-        if let Some(find) = characters_path.iter().find(|&x| x.0 == &name) {
-            load_characters(&mut state.character_manager, find.0, find.1).await;
+/// 清单中是否声明了该资源，语音则看语音目录中有没有该文件；脚本里未声明的资源不参与载入
+fn is_declared(manifest: &Manifest, audio: &AudioManager, asset: &SceneAsset) -> bool {
+    match asset {
+        SceneAsset::Background(name) => manifest.backgrounds.contains_key(name),
+        SceneAsset::Character(name) => manifest.character(name).is_some(),
+        SceneAsset::Music(name) => {
+            manifest.bgm.contains_key(name) || manifest.ambience.contains_key(name)
+        }
+        SceneAsset::Voice(name) => audio.voice_path(name).is_some(),
+    }
+}
+
+/// 载入一项场景资源，角色载入失败时用剪影占位，剧情照常进行
+async fn load_asset(
+    state: &mut GameState<'_>,
+    manifest: &Manifest,
+    asset: &SceneAsset,
+) -> StoryResult<()> {
+    match asset {
+        SceneAsset::Background(name) => {
+            let entry = manifest
+                .backgrounds
+                .get(name)
+                .ok_or_else(|| StoryError::missing("background", name))?;
+            state
+                .textures
+                .get_or_insert_with(TextureManager::new)
+                .load_texture_auto(&manifest.resolve(entry.path()), Some(name))
+                .await
+        }
+        SceneAsset::Character(name) => {
+            let entry = manifest
+                .character(name)
+                .ok_or_else(|| StoryError::missing("character", name))?;
+            let result = load_characters(&mut state.character_manager, manifest, name, entry).await;
+            if result.is_err() {
+                state.character_manager.register_placeholder(name.clone());
+            }
+            result
+        }
+        SceneAsset::Music(name) => {
+            let path = manifest
+                .bgm
+                .get(name)
+                .or_else(|| manifest.ambience.get(name))
+                .ok_or_else(|| StoryError::missing("music", name))?;
+            state.audio.load(name, &manifest.resolve(path)).await
+        }
+        SceneAsset::Voice(name) => {
+            let path = state
+                .audio
+                .voice_path(name)
+                .ok_or_else(|| StoryError::missing("voice", name))?
+                .to_string();
+            state.audio.load(name, &path).await
+        }
+    }
+}
+
+/// 画面上正在使用的资源，即使不在之后的 knot 中也不能释放
+fn assets_in_use(state: &GameState<'_>) -> Vec<SceneAsset> {
+    let backgrounds = state
+        .background
+        .iter()
+        .chain(state.transition.as_ref().and_then(|t| t.from.as_ref()))
+        .cloned()
+        .map(SceneAsset::Background);
+    let characters = state
+        .character_manager
+        .stage
+        .iter()
+        .map(|position| SceneAsset::Character(position.name.clone()));
+    let music = [
+        state.audio.current_bgm(),
+        state.audio.current_ambience(),
+        state.streamer.pending_bgm.as_deref(),
+        state.streamer.pending_ambience.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|name| SceneAsset::Music(name.to_string()));
+    // 当前台词的语音留着供重播
+    let voice = state
+        .speak_state
+        .voice
+        .iter()
+        .cloned()
+        .map(SceneAsset::Voice);
+    backgrounds
+        .chain(characters)
+        .chain(music)
+        .chain(voice)
+        .collect()
+}
+
+/// 资源的估算占用字节数，未载入时为 0
+fn asset_memory(state: &GameState<'_>, asset: &SceneAsset) -> usize {
+    match asset {
+        SceneAsset::Background(name) => state.textures.as_ref().map_or(0, |t| t.memory(name)),
+        SceneAsset::Character(name) => state
+            .character_manager
+            .characters
+            .get(name)
+            .map_or(0, |character| character.memory()),
+        SceneAsset::Music(name) | SceneAsset::Voice(name) => state.audio.memory(name),
+    }
+}
+
+/// 超出内存预算时按最久未用的顺序释放 `needed` 之外的资源，在场的角色与正在播放的音乐会被跳过
+fn evict_assets(state: &mut GameState<'_>, needed: &HashSet<SceneAsset>) {
+    let budget = state.streamer.budget_bytes();
+    let mut resident: usize = state
+        .streamer
+        .loaded
+        .keys()
+        .map(|asset| asset_memory(state, asset))
+        .sum();
+    for asset in state.streamer.eviction_order(needed) {
+        if resident <= budget {
+            break;
+        }
+        let memory = asset_memory(state, &asset);
+        let unloaded = match &asset {
+            SceneAsset::Background(name) => {
+                if let Some(textures) = state.textures.as_mut() {
+                    textures.remove(name);
+                }
+                true
+            }
+            SceneAsset::Character(name) => state.character_manager.unload(name),
+            SceneAsset::Music(name) | SceneAsset::Voice(name) => state.audio.unload(name),
+        };
+        if unloaded {
+            state.streamer.loaded.remove(&asset);
+            resident = resident.saturating_sub(memory);
+            println!("Unload {}.", asset.name());
+        }
+    }
+}
+
+/// 进入新的 knot 时载入之后几层跳转内用到的资源，并在超出预算时释放用不到的资源
+pub async fn stream_assets(state: &mut GameState<'_>, manifest: &Manifest) {
+    if !state.streamer.settings.enabled {
+        return;
+    }
+    // 一次 resume 可能跨过多个 knot：正在显示的台词所在的 knot 决定眼下要用的资源，
+    // resume 停下的 knot 决定接下来要用的资源
+    let (resume_knot, _) = state.engine.location();
+    let line_knot = state.engine.knot_line.0.clone();
+    let Some(upcoming) = state.streamer.enter(&[&line_knot, &resume_knot]) else {
+        return;
+    };
+    let mut needed = upcoming;
+    needed.extend(assets_in_use(state));
+    needed.retain(|asset| is_declared(manifest, &state.audio, asset));
+
+    let mut missing: Vec<SceneAsset> = needed
+        .iter()
+        .filter(|asset| {
+            !state.streamer.loaded.contains_key(*asset) && !state.streamer.failed.contains(*asset)
+        })
+        .cloned()
+        .collect();
+    missing.sort();
+    for asset in missing {
+        match load_asset(state, manifest, &asset).await {
+            Ok(()) => println!("Load {}.", asset.name()),
+            Err(e) => {
+                // 失败的资源不再重试，避免每次进入 knot 都卡一下
+                state.errors.push(e);
+                state.streamer.failed.insert(asset);
+            }
+        }
+    }
+    for asset in &needed {
+        if !state.streamer.failed.contains(asset) {
+            state.streamer.touch(asset);
+        }
+    }
+
+    // 读档时还没载入的音乐在载入后补上
+    if let Some(name) = state.streamer.pending_bgm.take() {
+        state.audio.play_bgm(Some(&name), STREAM_FADE);
+    }
+    if let Some(name) = state.streamer.pending_ambience.take() {
+        state.audio.play_ambience(Some(&name), STREAM_FADE);
+    }
+    evict_assets(state, &needed);
+}
+
+/// 启动时的一项载入任务
+enum LoadStep {
+    Font,
+    Sfx(String),
+    Asset(SceneAsset),
+    Texture(String),
+    Atlas,
+}
+
+impl LoadStep {
+    /// 载入画面上显示的名字
+    fn label<'m>(&'m self, manifest: &'m Manifest) -> &'m str {
+        match self {
+            LoadStep::Font => &manifest.font,
+            LoadStep::Sfx(name) | LoadStep::Texture(name) => name,
+            LoadStep::Asset(asset) => asset.name(),
+            LoadStep::Atlas => &manifest.atlas.yaml,
+        }
+    }
+}
+
+/// 启动时的资源载入，主循环每帧调用一次 [`ResourceLoader::step`] 载入一项，
+/// 载入之间照常刷新载入画面；载入失败的资源记录到错误提示中。
+/// 开启分段载入时只载入开头几个 knot 用到的背景、角色、音乐与语音，其余的在剧情推进时由
+/// [`stream_assets`] 载入
+pub struct ResourceLoader<'a> {
+    state: GameState<'a>,
+    steps: VecDeque<LoadStep>,
+    pub progress: LoadProgress,
+}
+
+impl<'a> ResourceLoader<'a> {
+    /// 读取故事并列出要载入的资源，故事无法读取时返回错误
+    pub fn new(manifest: &Manifest) -> StoryResult<Self> {
+        let story_path = manifest.resolve(&manifest.story);
+        let mut state = GameState::new(&story_path)?;
+        state.textures = Some(TextureManager::new());
+        println!("Resource load start.");
+
+        let index = state.engine.scenes().clone();
+        let mut unknown: Vec<String> = index
+            .all()
+            .into_iter()
+            .filter_map(|asset| match asset {
+                SceneAsset::Character(name) if manifest.character(&name).is_none() => Some(name),
+                _ => None,
+            })
+            .collect();
+        unknown.sort();
+        for name in unknown {
+            println!("Character {} not found in manifest, skipped.", name);
+        }
+
+        match state
+            .audio
+            .index_voices(&manifest.resolve(&manifest.voice_dir))
+        {
+            Ok(voices) => println!("Found {} voices.", voices),
+            Err(e) => state.errors.push(e),
+        }
+
+        let settings = manifest.streaming;
+        let mut initial: Vec<SceneAsset> = if settings.enabled {
+            let mut assets = index.upcoming(ROOT_KNOT, settings.lookahead);
+            assets.extend(
+                manifest
+                    .default_background
+                    .iter()
+                    .cloned()
+                    .map(SceneAsset::Background),
+            );
+            assets.extend(manifest.default_bgm.iter().cloned().map(SceneAsset::Music));
+            assets.into_iter().collect()
         } else {
-            load_characters(&mut state.character_manager, &name, &name).await;
+            let mut assets = index.all();
+            assets.extend(
+                manifest
+                    .backgrounds
+                    .keys()
+                    .cloned()
+                    .map(SceneAsset::Background),
+            );
+            assets.extend(
+                manifest
+                    .bgm
+                    .keys()
+                    .chain(manifest.ambience.keys())
+                    .cloned()
+                    .map(SceneAsset::Music),
+            );
+            assets.into_iter().collect()
+        };
+        initial.retain(|asset| is_declared(manifest, &state.audio, asset));
+        initial.sort();
+        state.streamer = SceneStreamer::new(index, settings);
+        state.streamer.enter(&[ROOT_KNOT]);
+
+        for (name, entry) in &manifest.backgrounds {
+            state
+                .background_views
+                .insert(name.clone(), entry.view(manifest.background_fit));
+        }
+        state.audio.volumes = manifest.volume;
+
+        // 字体最先载入，之后的载入画面才能显示中文；音效体积小且随时可能用到，总是在启动时载入
+        let mut steps = VecDeque::from([LoadStep::Font]);
+        steps.extend(manifest.sfx.keys().cloned().map(LoadStep::Sfx));
+        steps.extend(initial.into_iter().map(LoadStep::Asset));
+        steps.extend(manifest.textures.keys().cloned().map(LoadStep::Texture));
+        steps.push_back(LoadStep::Atlas);
+
+        let mut progress = LoadProgress::new(steps.len());
+        progress.begin(steps[0].label(manifest));
+        Ok(Self {
+            state,
+            steps,
+            progress,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// 载入下一项资源，字体以外的资源缺失时记录错误并继续，缺少的部分在游戏中留空
+    pub async fn step(&mut self, manifest: &Manifest) {
+        let Some(step) = self.steps.pop_front() else {
+            return;
+        };
+        let state = &mut self.state;
+        let progress = &mut self.progress;
+        match &step {
+            LoadStep::Font => {
+                let font = load_ttf_font(&manifest.resolve(&manifest.font)).await;
+                progress.finish(&font);
+                match font {
+                    Ok(font) => {
+                        progress.font = Some(font.clone());
+                        state.font = Some(font);
+                    }
+                    Err(e) => state.errors.push(format!(
+                        "Can not load font {}, fall back to default. {:?}",
+                        manifest.font, e
+                    )),
+                }
+            }
+            LoadStep::Sfx(name) => {
+                let result = match manifest.sfx.get(name) {
+                    Some(path) => state.audio.load(name, &manifest.resolve(path)).await,
+                    None => Err(StoryError::missing("sfx", name)),
+                };
+                progress.finish(&result);
+                if let Err(e) = result {
+                    state.errors.push(e);
+                }
+            }
+            LoadStep::Asset(asset) => {
+                let result = load_asset(state, manifest, asset).await;
+                progress.finish(&result);
+                match result {
+                    Ok(()) => state.streamer.touch(asset),
+                    Err(e) => {
+                        state.errors.push(e);
+                        state.streamer.failed.insert(asset.clone());
+                    }
+                }
+            }
+            LoadStep::Texture(name) => {
+                let result = match manifest.textures.get(name) {
+                    Some(path) => {
+                        state
+                            .textures
+                            .get_or_insert_with(TextureManager::new)
+                            .load_texture_auto(&manifest.resolve(path), Some(name))
+                            .await
+                    }
+                    None => Err(StoryError::missing("texture", name)),
+                };
+                progress.finish(&result);
+                if let Err(e) = result {
+                    state.errors.push(e);
+                }
+            }
+            LoadStep::Atlas => {
+                let atlas = SpriteAtlas::load(
+                    &manifest.resolve(&manifest.atlas.texture),
+                    &manifest.resolve(&manifest.atlas.yaml),
+                )
+                .await;
+                progress.finish(&atlas);
+                match atlas {
+                    Ok(atlas) => {
+                        println!("Loaded atlas with {} sprites.", atlas.sprites.len());
+                        state.atlas = Some(atlas);
+                    }
+                    Err(e) => state.errors.push(e),
+                }
+            }
+        }
+        if let Some(next) = self.steps.front() {
+            self.progress.begin(next.label(manifest));
         }
     }
-    textures
-        .load_texture_auto(
-            "/home/kina/temp/code/rust/spine/Texture2D/Growth_Bg.png",
-            Some("chioce_box"),
-        )
-        .await
-        .unwrap_or_else(|e| panic!("Can not load chioce box.{:?}", e));
-    println!("Load chioce box texture success.");
 
-    let atlas = SpriteAtlas::load("assets/Common.png", "assets/CommonAtlas.yaml").await;
-    println!("Loaded atlas with {} sprites.", atlas.sprites.len());
-    state.background = Some("background".to_string());
-    state.textures = Some(textures);
+    /// 全部载入后应用清单中的设置并处理故事的开头
+    pub fn finish(self, manifest: &Manifest) -> StoryResult<GameState<'a>> {
+        let mut state = self.state;
+        println!(
+            "Resource load finished, {} loaded, {} failed.",
+            self.progress.loaded, self.progress.failed
+        );
+        state.background = manifest.default_background.clone();
+        if let Some(view) = state
+            .background
+            .as_ref()
+            .and_then(|name| state.background_views.get(name))
+        {
+            state.background_view = *view;
+        }
+        state.save_manager = SaveManager::new(manifest.save_dir.clone(), SAVE_SLOTS);
+        state.reveal.speed = manifest.text_speed;
+        state.playback.skip_unread = manifest.skip_unread;
+        state.character_manager.highlight = manifest.highlight;
+        match ReadTracker::load(manifest.save_dir.join(READ_LINES_FILE)) {
+            Ok(read_lines) => state.read_lines = read_lines,
+            Err(e) => state.errors.push(format!("Can not load read lines. {}", e)),
+        }
+        state.audio.play_bgm(manifest.default_bgm.as_deref(), 0.0);
 
-    state.font = Some(font);
-    // state.sound = Some(sound);
-    state.atlas = Some(atlas);
+        // 资源就绪后再处理初始内容，保证第一句的演出指令能找到资源
+        state.advance_story()?;
 
-    Ok(state)
+        Ok(state)
+    }
 }
@@ -0,0 +1,180 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// 默认逐字显示速度（字/秒）
+pub const DEFAULT_TEXT_SPEED: f32 = 30.0;
+
+/// `{w=<秒>}` 停顿标记
+static PAUSE_MARKER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{w=([0-9]*\.?[0-9]+)\}").unwrap());
+
+/// 打字机效果的显示进度，支持 `{w=0.5}` 形式的停顿标记
+#[derive(Debug, Clone)]
+pub struct TextReveal {
+    /// 每秒显示的字符数，小于等于 0 时立即显示整句
+    pub speed: f32,
+    text: String,
+    /// (字符位置, 停顿秒数)，在显示到该位置时停顿
+    pauses: Vec<(usize, f32)>,
+    total: usize,
+    shown: f32,
+    pause_left: f32,
+    next_pause: usize,
+}
+
+impl TextReveal {
+    pub fn new(speed: f32) -> Self {
+        Self {
+            speed,
+            text: String::new(),
+            pauses: Vec::new(),
+            total: 0,
+            shown: 0.0,
+            pause_left: 0.0,
+            next_pause: 0,
+        }
+    }
+
+    /// 开始显示新的一句，返回去掉停顿标记后的文本
+    pub fn start(&mut self, content: &str) -> String {
+        let mut text = String::new();
+        let mut pauses = Vec::new();
+        let mut last = 0;
+        for captures in PAUSE_MARKER.captures_iter(content) {
+            let marker = captures.get(0).unwrap();
+            text.push_str(&content[last..marker.start()]);
+            let seconds = captures[1].parse::<f32>().unwrap_or(0.0);
+            pauses.push((text.chars().count(), seconds));
+            last = marker.end();
+        }
+        text.push_str(&content[last..]);
+
+        self.total = text.chars().count();
+        self.text = text.clone();
+        self.pauses = pauses;
+        self.shown = 0.0;
+        self.pause_left = 0.0;
+        self.next_pause = 0;
+        if self.speed <= 0.0 {
+            self.complete();
+        }
+        text
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        let mut delta_time = delta_time;
+        while delta_time > 0.0 && !self.is_complete() {
+            if self.pause_left > 0.0 {
+                let used = self.pause_left.min(delta_time);
+                self.pause_left -= used;
+                delta_time -= used;
+                continue;
+            }
+            let target = self.shown + delta_time * self.speed;
+            match self.pauses.get(self.next_pause) {
+                Some(&(position, seconds)) if target >= position as f32 => {
+                    // 走到停顿点，剩余时间先消耗在停顿上
+                    delta_time -= (position as f32 - self.shown).max(0.0) / self.speed;
+                    self.shown = position as f32;
+                    self.pause_left = seconds;
+                    self.next_pause += 1;
+                }
+                _ => {
+                    self.shown = target.min(self.total as f32);
+                    delta_time = 0.0;
+                }
+            }
+        }
+    }
+
+    /// 立即显示整句
+    pub fn complete(&mut self) {
+        self.shown = self.total as f32;
+        self.pause_left = 0.0;
+        self.next_pause = self.pauses.len();
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.shown as usize >= self.total
+    }
+
+    pub fn visible_chars(&self) -> usize {
+        (self.shown as usize).min(self.total)
+    }
+
+    pub fn visible_text(&self) -> &str {
+        match self.text.char_indices().nth(self.visible_chars()) {
+            Some((index, _)) => &self.text[..index],
+            None => &self.text,
+        }
+    }
+}
+
+impl Default for TextReveal {
+    fn default() -> Self {
+        Self::new(DEFAULT_TEXT_SPEED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markers_are_stripped() {
+        let mut reveal = TextReveal::new(4.0);
+        assert_eq!(reveal.start("等等{w=0.5}——！{w=1}"), "等等——！");
+        assert_eq!(reveal.pauses, vec![(2, 0.5), (5, 1.0)]);
+        // 不完整的标记原样保留
+        assert_eq!(reveal.start("{w=}{w=a}"), "{w=}{w=a}");
+        assert!(reveal.pauses.is_empty());
+    }
+
+    #[test]
+    fn update_waits_at_pauses() {
+        let mut reveal = TextReveal::new(4.0);
+        reveal.start("ab{w=0.5}cd");
+        reveal.update(0.5);
+        assert_eq!(reveal.visible_text(), "ab");
+        reveal.update(0.25);
+        assert_eq!(reveal.visible_text(), "ab");
+        // 停顿剩余 0.25 秒，其余时间继续显示
+        reveal.update(0.5);
+        assert_eq!(reveal.visible_text(), "abc");
+        assert!(!reveal.is_complete());
+        reveal.update(1.0);
+        assert_eq!(reveal.visible_text(), "abcd");
+        assert!(reveal.is_complete());
+    }
+
+    #[test]
+    fn update_spans_several_pauses() {
+        let mut reveal = TextReveal::new(4.0);
+        reveal.start("a{w=0.25}b{w=0.25}c");
+        reveal.update(1.25);
+        assert_eq!(reveal.visible_text(), "abc");
+    }
+
+    #[test]
+    fn complete_skips_pauses() {
+        let mut reveal = TextReveal::new(4.0);
+        reveal.start("ab{w=5}cd");
+        reveal.update(0.5);
+        reveal.complete();
+        assert!(reveal.is_complete());
+        assert_eq!(reveal.visible_chars(), 4);
+        assert_eq!(reveal.visible_text(), "abcd");
+        reveal.update(1.0);
+        assert_eq!(reveal.visible_text(), "abcd");
+    }
+
+    #[test]
+    fn non_positive_speed_shows_everything() {
+        for speed in [0.0, -1.0] {
+            let mut reveal = TextReveal::new(speed);
+            reveal.start("Sensei，{w=1}早上好。");
+            assert!(reveal.is_complete());
+            assert_eq!(reveal.visible_text(), "Sensei，早上好。");
+        }
+    }
+}
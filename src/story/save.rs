@@ -0,0 +1,478 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::story::{
+    BackgroundView, GameState, History, SpeakerState, StageCommand, StoryError, StoryResult,
+    character::StagePosition,
+};
+
+pub const SAVE_SLOTS: usize = 9;
+
+/// 存档槽位的摘要信息，供读档菜单列出
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveMeta {
+    pub slot: usize,
+    /// 存档时间（UNIX 秒）
+    pub timestamp: u64,
+    pub knot: String,
+    pub stitch: Option<String>,
+    /// 存档时显示的最后一句
+    pub preview: String,
+}
+
+/// 单个存档槽位的完整内容
+#[derive(Serialize, Deserialize)]
+pub struct SaveData {
+    pub meta: SaveMeta,
+    story: serde_yaml::Value,
+    line_buffer: Vec<inkling::Line>,
+    current_choices: Vec<inkling::Choice>,
+    is_choosing: bool,
+    is_choose: bool,
+    story_end: bool,
+    speaker: Option<String>,
+    #[serde(default)]
+    display_name: Option<String>,
+    animation: Option<String>,
+    content: String,
+    /// 当前台词的语音，读档后可在回看中重播
+    #[serde(default)]
+    voice: Option<String>,
+    background: Option<String>,
+    #[serde(default)]
+    background_view: BackgroundView,
+    bgm: Option<String>,
+    #[serde(default)]
+    ambience: Option<String>,
+    history: History,
+    stage: Vec<StagePosition>,
+    /// `# dim:` 设置的高亮开关，旧存档中没有时沿用当前设置
+    #[serde(default)]
+    highlight_enabled: Option<bool>,
+    #[serde(default)]
+    narration_lit: Option<bool>,
+    #[serde(default)]
+    knot_line: (String, usize),
+}
+
+impl SaveData {
+    pub fn capture(state: &GameState, slot: usize) -> StoryResult<Self> {
+        let (knot, stitch) = state.engine.location();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Ok(Self {
+            meta: SaveMeta {
+                slot,
+                timestamp,
+                knot,
+                stitch,
+                preview: state.speak_state.content.clone(),
+            },
+            story: serde_yaml::to_value(&state.engine.story)
+                .map_err(|e| save_error(slot, "Can not serialize story.", e))?,
+            line_buffer: state.engine.line_buffer.clone(),
+            current_choices: state.engine.current_choices.clone(),
+            is_choosing: state.engine.is_choosing,
+            is_choose: state.engine.is_choose,
+            story_end: state.engine.story_end,
+            speaker: state.speak_state.name.clone(),
+            display_name: state.speak_state.display_name.clone(),
+            animation: state.speak_state.animation.clone(),
+            content: state.speak_state.content.clone(),
+            voice: state.speak_state.voice.clone(),
+            background: state.background.clone(),
+            background_view: state.background_view,
+            bgm: state.audio.current_bgm().map(str::to_string),
+            ambience: state.audio.current_ambience().map(str::to_string),
+            history: state.history.clone(),
+            stage: state.character_manager.stage.clone(),
+            highlight_enabled: Some(state.character_manager.highlight.enabled),
+            narration_lit: Some(state.character_manager.highlight.narration_lit),
+            knot_line: state.engine.knot_line.clone(),
+        })
+    }
+
+    pub fn restore(self, state: &mut GameState) -> StoryResult<()> {
+        let slot = self.meta.slot;
+        state.engine.story = serde_yaml::from_value(self.story)
+            .map_err(|e| save_error(slot, "Story state is broken.", e))?;
+        state.engine.line_buffer = self.line_buffer;
+        state.engine.current_choices = self.current_choices;
+        state.engine.is_choosing = self.is_choosing;
+        state.engine.is_choose = self.is_choose;
+        state.engine.story_end = self.story_end;
+        state.engine.story_ended = false;
+        // 读档前等待资源的事件属于旧位置
+        state.engine.events.clear();
+        state.should_continue = true;
+        state.speak_state = SpeakerState {
+            name: self.speaker,
+            display_name: self.display_name,
+            animation: self.animation,
+            content: self.content,
+            voice: self.voice,
+            ..SpeakerState::new()
+        };
+        state.speak_state.content = state.reveal.start(&state.speak_state.content);
+        state.reveal.complete();
+        state.background = self.background;
+        state.background_view = self.background_view;
+        state.transition = None;
+        // 读档位置可能需要另一批资源
+        state.streamer.invalidate();
+        state.audio.stop_voice();
+        // 还没载入的音乐等分段载入完成后再播放
+        state.audio.play_bgm(
+            self.bgm
+                .as_deref()
+                .filter(|name| state.audio.contains(name)),
+            0.5,
+        );
+        state.audio.play_ambience(
+            self.ambience
+                .as_deref()
+                .filter(|name| state.audio.contains(name)),
+            0.5,
+        );
+        state.streamer.pending_bgm = self.bgm.filter(|name| !state.audio.contains(name));
+        state.streamer.pending_ambience = self.ambience.filter(|name| !state.audio.contains(name));
+        state.history = self.history;
+        state.character_manager.clear_stage();
+        state.character_manager.stage = self.stage;
+        let highlight = &mut state.character_manager.highlight;
+        highlight.enabled = self.highlight_enabled.unwrap_or(highlight.enabled);
+        highlight.narration_lit = self.narration_lit.unwrap_or(highlight.narration_lit);
+        state
+            .character_manager
+            .set_speaker(state.speak_state.name.as_deref());
+        state.engine.knot_line = self.knot_line;
+        state.current_line_read = true;
+        state.playback.stop();
+        if let Some(animation) = state.speak_state.animation.clone() {
+            state.execute_command(&StageCommand::Animation {
+                name: None,
+                animation,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// 按编号管理存档槽位，每个槽位对应存档目录下的一个 yaml 文件，
+/// 摘要另存一份小文件，列出槽位时不必解析整个故事
+pub struct SaveManager {
+    pub dir: PathBuf,
+    pub slots: usize,
+}
+
+impl SaveManager {
+    pub fn new(dir: impl Into<PathBuf>, slots: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            slots,
+        }
+    }
+
+    fn slot_path(&self, slot: usize) -> PathBuf {
+        self.dir.join(format!("slot_{:02}.yaml", slot))
+    }
+
+    fn meta_path(&self, slot: usize) -> PathBuf {
+        self.dir.join(format!("slot_{:02}.meta.yaml", slot))
+    }
+
+    fn check_slot(&self, slot: usize) -> StoryResult<()> {
+        if slot < self.slots {
+            Ok(())
+        } else {
+            Err(StoryError::Save {
+                slot,
+                message: format!("Out of range 0..{}.", self.slots),
+            })
+        }
+    }
+
+    pub fn save(&self, slot: usize, state: &GameState) -> StoryResult<SaveMeta> {
+        self.check_slot(slot)?;
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| save_error(slot, format!("Can not create save dir {:?}.", self.dir), e))?;
+        let data = SaveData::capture(state, slot)?;
+        let content =
+            serde_yaml::to_string(&data).map_err(|e| save_error(slot, "Can not serialize.", e))?;
+        fs::write(self.slot_path(slot), content)
+            .map_err(|e| save_error(slot, "Can not write save.", e))?;
+        let meta = serde_yaml::to_string(&data.meta)
+            .map_err(|e| save_error(slot, "Can not serialize meta.", e))?;
+        fs::write(self.meta_path(slot), meta)
+            .map_err(|e| save_error(slot, "Can not write meta.", e))?;
+        Ok(data.meta)
+    }
+
+    pub fn read(&self, slot: usize) -> StoryResult<SaveData> {
+        self.check_slot(slot)?;
+        let content = fs::read_to_string(self.slot_path(slot))
+            .map_err(|e| save_error(slot, "Can not read save.", e))?;
+        serde_yaml::from_str(&content).map_err(|e| save_error(slot, "Save is broken.", e))
+    }
+
+    /// 只读取槽位的摘要；没有摘要文件的旧存档读取完整存档
+    pub fn read_meta(&self, slot: usize) -> StoryResult<SaveMeta> {
+        self.check_slot(slot)?;
+        let path = self.meta_path(slot);
+        if !path.exists() {
+            return Ok(self.read(slot)?.meta);
+        }
+        let content =
+            fs::read_to_string(path).map_err(|e| save_error(slot, "Can not read meta.", e))?;
+        serde_yaml::from_str(&content).map_err(|e| save_error(slot, "Meta is broken.", e))
+    }
+
+    /// 列出全部槽位，空槽位或损坏的存档为 `None`
+    pub fn list(&self) -> Vec<Option<SaveMeta>> {
+        (0..self.slots)
+            .map(|slot| {
+                if self.slot_path(slot).exists() {
+                    self.read_meta(slot).ok()
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn delete(&self, slot: usize) -> StoryResult<()> {
+        self.check_slot(slot)?;
+        for path in [self.slot_path(slot), self.meta_path(slot)] {
+            if path.exists() {
+                fs::remove_file(path).map_err(|e| save_error(slot, "Can not delete.", e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 存档菜单的用途
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveMenuMode {
+    Save,
+    Load,
+}
+
+/// 选择存档槽位的菜单，打开时读取各槽位的摘要
+#[derive(Debug, Clone, Default)]
+pub struct SaveMenu {
+    /// `None` 表示菜单关闭
+    pub mode: Option<SaveMenuMode>,
+    pub slots: Vec<Option<SaveMeta>>,
+    /// 方向键选中的槽位
+    pub focused: usize,
+}
+
+impl SaveMenu {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(&mut self, mode: SaveMenuMode, manager: &SaveManager) {
+        self.mode = Some(mode);
+        self.slots = manager.list();
+        // 读档时默认选中最近的存档
+        self.focused = match mode {
+            SaveMenuMode::Save => 0,
+            SaveMenuMode::Load => self
+                .slots
+                .iter()
+                .enumerate()
+                .filter_map(|(slot, meta)| Some((slot, meta.as_ref()?.timestamp)))
+                .max_by_key(|(_, timestamp)| *timestamp)
+                .map_or(0, |(slot, _)| slot),
+        };
+    }
+
+    pub fn close(&mut self) {
+        self.mode = None;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.mode.is_some()
+    }
+
+    /// 方向键移动焦点，`delta` 为 -1 或 1，首尾循环
+    pub fn move_focus(&mut self, delta: i32) {
+        if self.slots.is_empty() {
+            return;
+        }
+        let count = self.slots.len() as i32;
+        self.focused = (self.focused as i32 + delta).rem_euclid(count) as usize;
+    }
+}
+
+impl GameState<'_> {
+    /// 在菜单中选择槽位：存档后关闭菜单，读档时空槽位不做任何事
+    pub fn choose_save_slot(&mut self, slot: usize) -> StoryResult<()> {
+        let Some(mode) = self.save_menu.mode else {
+            return Ok(());
+        };
+        match mode {
+            SaveMenuMode::Save => {
+                let meta = self.save_slot(slot)?;
+                println!("Saved slot {} at {}.", meta.slot, meta.knot);
+            }
+            SaveMenuMode::Load => {
+                if self.save_menu.slots.get(slot).is_none_or(Option::is_none) {
+                    return Ok(());
+                }
+                self.load_slot(slot)?;
+            }
+        }
+        self.save_menu.close();
+        Ok(())
+    }
+
+    /// 存档时顺便写回已读记录
+    pub fn save_slot(&mut self, slot: usize) -> StoryResult<SaveMeta> {
+        self.read_lines.flush();
+        self.save_manager.save(slot, self)
+    }
+
+    pub fn load_slot(&mut self, slot: usize) -> StoryResult<()> {
+        let data = self.save_manager.read(slot)?;
+        data.restore(self)
+    }
+}
+
+fn save_error(slot: usize, context: impl Display, error: impl Display) -> StoryError {
+    StoryError::Save {
+        slot,
+        message: format!("{} {}", context, error),
+    }
+}
+
+impl Default for SaveManager {
+    fn default() -> Self {
+        Self::new("saves", SAVE_SLOTS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::story::{HISTORY_CAPACITY, character::StageSlot};
+
+    fn sample() -> SaveData {
+        let mut history = History::new(HISTORY_CAPACITY);
+        history.record_line(&SpeakerState {
+            name: Some("阿罗娜".to_string()),
+            content: "Sensei，早上好。".to_string(),
+            voice: Some("阿罗娜/$ROOT$_0".to_string()),
+            ..SpeakerState::new()
+        });
+        history.record_choice("先处理完手头这份报告吧。");
+        SaveData {
+            meta: SaveMeta {
+                slot: 3,
+                timestamp: 1_700_000_000,
+                knot: "report".to_string(),
+                stitch: Some("later".to_string()),
+                preview: "当然。".to_string(),
+            },
+            story: serde_yaml::Value::Null,
+            line_buffer: Vec::new(),
+            current_choices: Vec::new(),
+            is_choosing: false,
+            is_choose: false,
+            story_end: false,
+            speaker: Some("阿罗娜".to_string()),
+            display_name: Some("???".to_string()),
+            animation: Some("Idle_01".to_string()),
+            content: "当然。".to_string(),
+            voice: Some("阿罗娜/report_0".to_string()),
+            background: Some("BG_GameDevRoom".to_string()),
+            background_view: BackgroundView::default(),
+            bgm: Some("Track_64".to_string()),
+            ambience: None,
+            history,
+            stage: vec![StagePosition {
+                name: "阿罗娜".to_string(),
+                slot: StageSlot::Center,
+                scale: 1.0,
+            }],
+            highlight_enabled: Some(false),
+            narration_lit: Some(true),
+            knot_line: ("report".to_string(), 1),
+        }
+    }
+
+    #[test]
+    fn load_menu_lists_slots_and_focuses_the_latest_save() {
+        let dir = std::env::temp_dir().join(format!("save_menu_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let manager = SaveManager::new(&dir, SAVE_SLOTS);
+        let mut meta = sample().meta;
+        for (slot, timestamp) in [(3, 100), (5, 200), (7, 150)] {
+            meta.slot = slot;
+            meta.timestamp = timestamp;
+            fs::write(manager.slot_path(slot), "").unwrap();
+            fs::write(
+                manager.meta_path(slot),
+                serde_yaml::to_string(&meta).unwrap(),
+            )
+            .unwrap();
+        }
+
+        let mut menu = SaveMenu::new();
+        menu.open(SaveMenuMode::Load, &manager);
+        assert_eq!(menu.slots.len(), SAVE_SLOTS);
+        assert!(menu.slots[3].is_some() && menu.slots[4].is_none());
+        assert_eq!(menu.focused, 5);
+        menu.move_focus(1);
+        menu.move_focus(1);
+        menu.move_focus(1);
+        menu.move_focus(1);
+        assert_eq!(menu.focused, 0);
+        menu.move_focus(-1);
+        assert_eq!(menu.focused, SAVE_SLOTS - 1);
+
+        menu.open(SaveMenuMode::Save, &manager);
+        assert_eq!(menu.focused, 0);
+        menu.close();
+        assert!(!menu.is_open());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_data_round_trips() {
+        let yaml = serde_yaml::to_string(&sample()).unwrap();
+        let data: SaveData = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(serde_yaml::to_string(&data).unwrap(), yaml);
+        assert_eq!(data.meta.slot, 3);
+        assert_eq!(data.meta.stitch.as_deref(), Some("later"));
+        assert_eq!(data.voice.as_deref(), Some("阿罗娜/report_0"));
+        assert_eq!(data.knot_line, ("report".to_string(), 1));
+        assert_eq!(data.highlight_enabled, Some(false));
+        assert_eq!(data.stage[0].name, "阿罗娜");
+        assert_eq!(data.history.len(), 2);
+        assert_eq!(
+            data.history.entries()[0].voice.as_deref(),
+            Some("阿罗娜/$ROOT$_0")
+        );
+    }
+
+    #[test]
+    fn old_saves_without_new_fields_still_load() {
+        let mut value = serde_yaml::to_value(sample()).unwrap();
+        let map = value.as_mapping_mut().unwrap();
+        for key in ["voice", "highlight_enabled", "narration_lit", "knot_line"] {
+            map.remove(key);
+        }
+        let data: SaveData = serde_yaml::from_value(value).unwrap();
+        assert_eq!(data.voice, None);
+        assert_eq!(data.highlight_enabled, None);
+        assert_eq!(data.knot_line, (String::new(), 0));
+    }
+}
@@ -0,0 +1,528 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::story::{SpeakerLine, StageCommand, script_text, voice_name};
+
+/// 按 knot 分段载入资源的设置
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StreamingSettings {
+    /// 关闭时启动时载入全部资源
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// 预载几层跳转之后的 knot，一批台词可能连续跨过多个 knot，不宜小于 1
+    #[serde(default = "default_lookahead")]
+    pub lookahead: usize,
+    /// 背景、角色、音乐与语音的内存预算（MB），超出时按最久未用的顺序释放当前用不到的资源
+    #[serde(default = "default_budget")]
+    pub budget_mb: f32,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_lookahead() -> usize {
+    2
+}
+
+fn default_budget() -> f32 {
+    512.0
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            lookahead: default_lookahead(),
+            budget_mb: default_budget(),
+        }
+    }
+}
+
+/// 可按场景载入与释放的资源
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SceneAsset {
+    Background(String),
+    Character(String),
+    /// BGM 或环境音
+    Music(String),
+    /// `<角色>/<文件名>` 形式的语音
+    Voice(String),
+}
+
+impl SceneAsset {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Background(name)
+            | Self::Character(name)
+            | Self::Music(name)
+            | Self::Voice(name) => name,
+        }
+    }
+}
+
+/// inkling 中第一个 knot 之前内容所在的位置名
+pub const ROOT_KNOT: &str = "$ROOT$";
+
+/// 一个 knot 中引用的资源、跳转目标与会显示的台词
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KnotAssets {
+    pub assets: HashSet<SceneAsset>,
+    pub next: Vec<String>,
+    /// 按脚本顺序排列的台词文本，stitch 中的台词也计入所在的 knot
+    pub lines: Vec<String>,
+}
+
+/// ink 脚本中各 knot 引用的资源，第一个 knot 之前的内容记在 [`ROOT_KNOT`] 下
+#[derive(Debug, Clone, Default)]
+pub struct SceneIndex {
+    knots: HashMap<String, KnotAssets>,
+}
+
+impl SceneIndex {
+    pub fn analyse(source: &str) -> Self {
+        let mut knots: HashMap<String, KnotAssets> = HashMap::new();
+        let mut knot = ROOT_KNOT.to_string();
+        for line in source.lines() {
+            let line = line.trim();
+            if line.starts_with("//") {
+                continue;
+            }
+            // `== knot ==` 开始新的 knot，`= stitch` 仍属于当前 knot
+            if let Some(header) = line.strip_prefix("==") {
+                knot = knot_name(header).unwrap_or_default();
+                continue;
+            }
+            if line.starts_with('=') {
+                continue;
+            }
+            let entry = knots.entry(knot.clone()).or_default();
+            for target in diverts(line) {
+                if !entry.next.contains(&target) {
+                    entry.next.push(target);
+                }
+            }
+            let commands: Vec<StageCommand> = line
+                .split('#')
+                .skip(1)
+                .filter_map(|tag| StageCommand::parse(tag).ok())
+                .collect();
+            entry
+                .assets
+                .extend(commands.iter().filter_map(command_asset));
+            if let Some(text) = script_text(line) {
+                if let Some(speaker) = SpeakerLine::parse(text).speaker {
+                    let tagged = commands
+                        .iter()
+                        .any(|command| matches!(command, StageCommand::Voice { .. }));
+                    if !tagged {
+                        let voice = voice_name(&speaker, &knot, entry.lines.len());
+                        entry.assets.insert(SceneAsset::Voice(voice));
+                    }
+                    entry.assets.insert(SceneAsset::Character(speaker));
+                }
+                entry.lines.push(text.to_string());
+            }
+        }
+        Self { knots }
+    }
+
+    pub fn knot(&self, name: &str) -> Option<&KnotAssets> {
+        self.knots.get(name)
+    }
+
+    /// 脚本中引用的全部资源
+    pub fn all(&self) -> HashSet<SceneAsset> {
+        self.knots
+            .values()
+            .flat_map(|knot| knot.assets.iter().cloned())
+            .collect()
+    }
+
+    /// 未知的 knot 按第一个 knot 之前的内容处理
+    fn start<'a>(&self, knot: &'a str) -> &'a str {
+        if self.knots.contains_key(knot) {
+            knot
+        } else {
+            ROOT_KNOT
+        }
+    }
+
+    /// 从 `start` 出发 `lookahead` 次跳转内可到达的 knot，由近及远排列，`None` 表示不限层数
+    fn reachable(&self, start: &str, lookahead: Option<usize>) -> Vec<String> {
+        let mut knots = Vec::new();
+        let mut visited = HashSet::from([start.to_string()]);
+        let mut queue = VecDeque::from([(start.to_string(), 0)]);
+        while let Some((name, depth)) = queue.pop_front() {
+            let Some(entry) = self.knots.get(&name) else {
+                continue;
+            };
+            knots.push(name);
+            if lookahead.is_some_and(|lookahead| depth >= lookahead) {
+                continue;
+            }
+            for next in &entry.next {
+                if visited.insert(next.clone()) {
+                    queue.push_back((next.clone(), depth + 1));
+                }
+            }
+        }
+        knots
+    }
+
+    /// `knot` 及 `lookahead` 次跳转内可到达的 knot 引用的资源
+    pub fn upcoming(&self, knot: &str, lookahead: usize) -> HashSet<SceneAsset> {
+        self.reachable(self.start(knot), Some(lookahead))
+            .iter()
+            .flat_map(|name| self.knots[name].assets.iter().cloned())
+            .collect()
+    }
+
+    /// 台词所在的 knot 及其在 knot 中的序号。依次查找 `from` 中第 `after` 句及之后、
+    /// 从 `from` 按跳转由近及远可到达的 knot 与其余 knot，脚本中找不到时返回 `None`
+    pub fn locate(&self, from: &str, after: usize, text: &str) -> Option<(String, usize)> {
+        let text = text.trim();
+        let find = |name: &str, skip: usize| {
+            let lines = &self.knots.get(name)?.lines;
+            let index = lines.iter().skip(skip).position(|line| line == text)?;
+            Some((name.to_string(), skip + index))
+        };
+        let start = self.start(from);
+        if let Some(found) = find(start, after) {
+            return Some(found);
+        }
+        let mut names = self.reachable(start, None);
+        let mut rest: Vec<&String> = self.knots.keys().filter(|k| !names.contains(k)).collect();
+        rest.sort();
+        names.extend(rest.into_iter().cloned());
+        names.iter().find_map(|name| find(name, 0))
+    }
+}
+
+/// knot 声明去掉 `=` 后的名字，`function` 与参数表不计入
+fn knot_name(header: &str) -> Option<String> {
+    let header = header.trim_matches(|c: char| c == '=' || c.is_whitespace());
+    let header = header.strip_prefix("function ").unwrap_or(header);
+    header
+        .split(|c: char| c == '(' || c.is_whitespace())
+        .next()
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+/// 一行中 `-> 目标` 跳转到的 knot，`END`、`DONE` 与隧道返回不计入
+fn diverts(line: &str) -> Vec<String> {
+    let line = line.split('#').next().unwrap_or_default();
+    line.split("->")
+        .skip(1)
+        .filter_map(|rest| {
+            let target = rest
+                .trim_start()
+                .split(|c: char| c.is_whitespace() || c == '.' || c == '(')
+                .next()?;
+            (!target.is_empty() && target != "END" && target != "DONE").then(|| target.to_string())
+        })
+        .collect()
+}
+
+/// 指令中明确写出的资源，未写角色名的指令作用于说话人，已由台词计入
+fn command_asset(command: &StageCommand) -> Option<SceneAsset> {
+    match command {
+        StageCommand::Background { name, .. } => Some(SceneAsset::Background(name.clone())),
+        StageCommand::Bgm {
+            name: Some(name), ..
+        }
+        | StageCommand::Ambience {
+            name: Some(name), ..
+        } => Some(SceneAsset::Music(name.clone())),
+        StageCommand::Voice { name } => Some(SceneAsset::Voice(name.clone())),
+        StageCommand::Show { name, .. } | StageCommand::Hide { name, .. } => {
+            Some(SceneAsset::Character(name.clone()))
+        }
+        StageCommand::Motion {
+            name: Some(name), ..
+        }
+        | StageCommand::Animation {
+            name: Some(name), ..
+        }
+        | StageCommand::Track {
+            name: Some(name), ..
+        }
+        | StageCommand::Mix {
+            name: Some(name), ..
+        }
+        | StageCommand::Skin {
+            name: Some(name), ..
+        }
+        | StageCommand::Blink {
+            name: Some(name), ..
+        }
+        | StageCommand::Attachment {
+            name: Some(name), ..
+        } => Some(SceneAsset::Character(name.clone())),
+        _ => None,
+    }
+}
+
+/// 运行时的分段载入状态：当前场景与各资源最近一次被需要的时间
+#[derive(Debug, Clone, Default)]
+pub struct SceneStreamer {
+    pub index: SceneIndex,
+    pub settings: StreamingSettings,
+    /// 上次处理的 knot，为空表示需要重新计算
+    pub knots: Vec<String>,
+    /// 已载入的资源及其最近一次被需要时的 `tick`
+    pub loaded: HashMap<SceneAsset, u64>,
+    /// 载入失败的资源，不再重试
+    pub failed: HashSet<SceneAsset>,
+    /// 读档时尚未载入的 BGM 与环境音，载入后开始播放
+    pub pending_bgm: Option<String>,
+    pub pending_ambience: Option<String>,
+    tick: u64,
+}
+
+impl SceneStreamer {
+    pub fn new(index: SceneIndex, settings: StreamingSettings) -> Self {
+        Self {
+            index,
+            settings,
+            ..Self::default()
+        }
+    }
+
+    /// 读档等跳转之后调用，下一帧重新计算需要的资源
+    pub fn invalidate(&mut self) {
+        self.knots.clear();
+    }
+
+    /// 位于 `knots` 时需要的资源，即各 knot 之后几层跳转内资源的并集，与上次相同时返回 `None`
+    pub fn enter(&mut self, knots: &[&str]) -> Option<HashSet<SceneAsset>> {
+        let mut knots: Vec<String> = knots.iter().map(|knot| knot.to_string()).collect();
+        knots.sort();
+        knots.dedup();
+        if self.knots == knots {
+            return None;
+        }
+        self.tick += 1;
+        let assets = knots
+            .iter()
+            .flat_map(|knot| self.index.upcoming(knot, self.settings.lookahead))
+            .collect();
+        self.knots = knots;
+        Some(assets)
+    }
+
+    /// `knot` 需要的资源是否已经载入过；未开启分段载入时总是 `true`
+    pub fn is_ready(&self, knot: &str) -> bool {
+        !self.settings.enabled || self.knots.iter().any(|entered| entered == knot)
+    }
+
+    /// 记录资源在本场景中被用到
+    pub fn touch(&mut self, asset: &SceneAsset) {
+        self.loaded.insert(asset.clone(), self.tick);
+    }
+
+    pub fn budget_bytes(&self) -> usize {
+        (self.settings.budget_mb.max(0.0) * 1024.0 * 1024.0) as usize
+    }
+
+    /// 不在 `needed` 中的已载入资源，最久未用的在前
+    pub fn eviction_order(&self, needed: &HashSet<SceneAsset>) -> Vec<SceneAsset> {
+        let mut candidates: Vec<(&SceneAsset, u64)> = self
+            .loaded
+            .iter()
+            .filter(|(asset, _)| !needed.contains(*asset))
+            .map(|(asset, tick)| (asset, *tick))
+            .collect();
+        candidates.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(b.0)));
+        candidates
+            .into_iter()
+            .map(|(asset, _)| asset.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STORY: &str = "\
+阿罗娜: Sensei，早上好。 # bg: BG_Classroom
++ [报告] -> report
++ [点心]
+ -> noreport
+
+==report==
+阿罗娜: 当然。 # bgm: calm fade=1
+= later
+普拉娜: 我先去准备！
+ -> end
+=== noreport ===
+普拉娜: 耶！ # show: 阿罗娜 at=left # voice: 普拉娜/yay
+ -> end
+
+== end ==
+黑见芹香: 等等——！ # amb: rain
+早濑优香: 经费！ # bg: BG_Office
+ -> END
+";
+
+    fn character(name: &str) -> SceneAsset {
+        SceneAsset::Character(name.to_string())
+    }
+
+    #[test]
+    fn knots_collect_assets_and_diverts() {
+        let index = SceneIndex::analyse(STORY);
+        let root = index.knot(ROOT_KNOT).unwrap();
+        assert!(root.assets.contains(&character("阿罗娜")));
+        assert!(
+            root.assets
+                .contains(&SceneAsset::Background("BG_Classroom".to_string()))
+        );
+        assert_eq!(root.next, vec!["report", "noreport"]);
+
+        let report = index.knot("report").unwrap();
+        assert!(report.assets.contains(&character("普拉娜")));
+        assert!(
+            report
+                .assets
+                .contains(&SceneAsset::Music("calm".to_string()))
+        );
+        assert_eq!(report.next, vec!["end"]);
+
+        assert!(
+            index
+                .knot("noreport")
+                .unwrap()
+                .assets
+                .contains(&character("阿罗娜"))
+        );
+        assert!(index.knot("end").unwrap().next.is_empty());
+    }
+
+    #[test]
+    fn voices_follow_the_knot_of_each_line() {
+        let index = SceneIndex::analyse(STORY);
+        let voice = |name: &str| SceneAsset::Voice(name.to_string());
+        assert!(
+            index
+                .knot(ROOT_KNOT)
+                .unwrap()
+                .assets
+                .contains(&voice("阿罗娜/$ROOT$_0"))
+        );
+        let report = &index.knot("report").unwrap().assets;
+        assert!(report.contains(&voice("阿罗娜/report_0")));
+        assert!(report.contains(&voice("普拉娜/report_1")));
+        // 标签指定的语音代替按约定生成的语音
+        let noreport = &index.knot("noreport").unwrap().assets;
+        assert!(noreport.contains(&voice("普拉娜/yay")));
+        assert!(!noreport.contains(&voice("普拉娜/noreport_0")));
+        assert!(
+            index
+                .knot("end")
+                .unwrap()
+                .assets
+                .contains(&voice("早濑优香/end_1"))
+        );
+    }
+
+    #[test]
+    fn lines_are_located_in_their_knot() {
+        let index = SceneIndex::analyse(STORY);
+        assert_eq!(
+            index.knot("report").unwrap().lines,
+            vec!["阿罗娜: 当然。", "普拉娜: 我先去准备！"]
+        );
+        let locate = |from: &str, after: usize, text: &str| index.locate(from, after, text);
+        assert_eq!(
+            locate(ROOT_KNOT, 0, "阿罗娜: Sensei，早上好。 \n"),
+            Some((ROOT_KNOT.to_string(), 0))
+        );
+        // 无论从哪个分支过来，同一句台词的位置相同
+        assert_eq!(
+            locate("report", 2, "黑见芹香: 等等——！"),
+            Some(("end".to_string(), 0))
+        );
+        assert_eq!(
+            locate("noreport", 1, "黑见芹香: 等等——！"),
+            Some(("end".to_string(), 0))
+        );
+        assert_eq!(
+            locate(ROOT_KNOT, 1, "普拉娜: 我先去准备！"),
+            Some(("report".to_string(), 1))
+        );
+        assert_eq!(locate("end", 0, "没有这句"), None);
+    }
+
+    #[test]
+    fn upcoming_follows_lookahead() {
+        let index = SceneIndex::analyse(STORY);
+        let near = index.upcoming("report", 0);
+        assert!(near.contains(&character("普拉娜")));
+        assert!(!near.contains(&character("黑见芹香")));
+        assert!(index.upcoming("report", 1).contains(&character("黑见芹香")));
+        // 未知的 knot 从脚本开头算起
+        assert!(index.upcoming("missing", 1).contains(&character("普拉娜")));
+    }
+
+    #[test]
+    fn entering_covers_every_knot() {
+        let settings = StreamingSettings {
+            lookahead: 0,
+            ..StreamingSettings::default()
+        };
+        let mut streamer = SceneStreamer::new(SceneIndex::analyse(STORY), settings);
+        // 台词还在开头，resume 已经停在 end
+        let assets = streamer.enter(&[ROOT_KNOT, "end"]).unwrap();
+        assert!(assets.contains(&character("阿罗娜")));
+        assert!(assets.contains(&character("黑见芹香")));
+        assert!(!assets.contains(&character("普拉娜")));
+        assert_eq!(streamer.enter(&["end", ROOT_KNOT]), None);
+        assert!(streamer.enter(&["end"]).is_some());
+    }
+
+    #[test]
+    fn knots_wait_for_their_own_assets() {
+        let settings = StreamingSettings {
+            lookahead: 0,
+            ..StreamingSettings::default()
+        };
+        let mut streamer = SceneStreamer::new(SceneIndex::analyse(STORY), settings);
+        streamer.enter(&[ROOT_KNOT]);
+        // 不预载时 end 的台词要等进入 end 载入背景后才能显示
+        assert!(!streamer.is_ready("end"));
+        let assets = streamer.enter(&["end"]).unwrap();
+        assert!(assets.contains(&SceneAsset::Background("BG_Office".to_string())));
+        assert!(streamer.is_ready("end"));
+
+        let disabled = SceneStreamer::new(
+            SceneIndex::analyse(STORY),
+            StreamingSettings {
+                enabled: false,
+                ..settings
+            },
+        );
+        assert!(disabled.is_ready("end"));
+    }
+
+    #[test]
+    fn eviction_prefers_least_recently_used() {
+        let mut streamer =
+            SceneStreamer::new(SceneIndex::analyse(STORY), StreamingSettings::default());
+        streamer.enter(&["report"]);
+        streamer.touch(&character("普拉娜"));
+        streamer.touch(&character("阿罗娜"));
+        streamer.enter(&["end"]);
+        streamer.touch(&character("黑见芹香"));
+        streamer.touch(&character("阿罗娜"));
+        assert_eq!(streamer.enter(&["end"]), None);
+
+        let needed = HashSet::from([character("黑见芹香")]);
+        assert_eq!(
+            streamer.eviction_order(&needed),
+            vec![character("普拉娜"), character("阿罗娜")]
+        );
+    }
+}
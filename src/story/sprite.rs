@@ -5,6 +5,8 @@ use serde::Serialize;
 use std::collections::HashMap;
 use std::fs;
 
+use crate::story::{StoryError, StoryResult};
+
 #[derive(Debug, Clone, Copy)]
 pub struct SpriteDrawParams {
     pub scale: f32,
@@ -58,6 +60,58 @@ impl Sprite {
             self.border_bottom,
         )
     }
+    /// 九宫格切分，按行优先返回 9 组（源矩形, 目标矩形）
+    /// 边框乘以 `scale` 后超出 `dest` 时按比例缩小，宽或高为 0 的格子由调用方跳过
+    pub fn nine_slice(&self, dest: Rect, scale: f32) -> [(Rect, Rect); 9] {
+        let (border_left, border_right, border_top, border_bottom) = self.border_rect();
+        let shrink_x = (dest.w / ((border_left + border_right) * scale)).min(1.0);
+        let shrink_y = (dest.h / ((border_top + border_bottom) * scale)).min(1.0);
+        let scale_x = scale * if shrink_x.is_finite() { shrink_x } else { 1.0 };
+        let scale_y = scale * if shrink_y.is_finite() { shrink_y } else { 1.0 };
+
+        let src_x = [
+            self.x,
+            self.x + border_left,
+            self.x + self.width - border_right,
+            self.x + self.width,
+        ];
+        let src_y = [
+            self.y,
+            self.y + border_top,
+            self.y + self.height - border_bottom,
+            self.y + self.height,
+        ];
+        let dest_x = [
+            dest.x,
+            dest.x + border_left * scale_x,
+            dest.x + dest.w - border_right * scale_x,
+            dest.x + dest.w,
+        ];
+        let dest_y = [
+            dest.y,
+            dest.y + border_top * scale_y,
+            dest.y + dest.h - border_bottom * scale_y,
+            dest.y + dest.h,
+        ];
+
+        std::array::from_fn(|i| {
+            let (row, column) = (i / 3, i % 3);
+            (
+                Rect::new(
+                    src_x[column],
+                    src_y[row],
+                    src_x[column + 1] - src_x[column],
+                    src_y[row + 1] - src_y[row],
+                ),
+                Rect::new(
+                    dest_x[column],
+                    dest_y[row],
+                    dest_x[column + 1] - dest_x[column],
+                    dest_y[row + 1] - dest_y[row],
+                ),
+            )
+        })
+    }
 }
 
 pub struct SpriteAtlas {
@@ -66,22 +120,32 @@ pub struct SpriteAtlas {
 }
 
 impl SpriteAtlas {
-    pub async fn load(texture_path: &str, yaml_path: &str) -> Self {
-        let texture = load_texture(texture_path).await.unwrap();
-        let yaml_content = fs::read_to_string(yaml_path).unwrap();
+    pub async fn load(texture_path: &str, yaml_path: &str) -> StoryResult<Self> {
+        let texture = load_texture(texture_path)
+            .await
+            .map_err(|e| StoryError::Texture {
+                path: texture_path.to_string(),
+                message: format!("{:?}", e),
+            })?;
+        let yaml_content =
+            fs::read_to_string(yaml_path).map_err(|e| StoryError::io(yaml_path, e))?;
         #[derive(Debug, Deserialize)]
         struct YamlFile {
             m_sprites: Vec<Sprite>,
         }
-        let yaml_data: YamlFile = serde_yaml::from_str(&yaml_content).unwrap();
+        let yaml_data: YamlFile =
+            serde_yaml::from_str(&yaml_content).map_err(|e| StoryError::Parse {
+                path: yaml_path.to_string(),
+                message: e.to_string(),
+            })?;
         let mut sprites = HashMap::new();
         for sprite in yaml_data.m_sprites {
             sprites.insert(sprite.name.clone(), sprite);
         }
-        SpriteAtlas {
+        Ok(SpriteAtlas {
             texture: texture,
             sprites: sprites,
-        }
+        })
     }
     pub fn get_sprite(&self, name: &str) -> Option<&Sprite> {
         self.sprites.get(name)
@@ -91,13 +155,12 @@ impl SpriteAtlas {
         name: &str,
         postion: Vec2,
         params: SpriteDrawParams,
-    ) -> Result<(), String> {
-        if let Some(sprite) = self.get_sprite(name) {
-            self.draw_sprite_definition(sprite, postion, params);
-            Ok(())
-        } else {
-            Err("Failed to draw sprite".to_string())
-        }
+    ) -> StoryResult<()> {
+        let sprite = self
+            .get_sprite(name)
+            .ok_or_else(|| StoryError::missing("sprite", name))?;
+        self.draw_sprite_definition(sprite, postion, params);
+        Ok(())
     }
     pub fn draw_sprite_definition(&self, sprite: &Sprite, postion: Vec2, params: SpriteDrawParams) {
         let src_rect = sprite.source_rect();
@@ -113,7 +176,7 @@ impl SpriteAtlas {
             dest_rect.y,
             params.color,
             DrawTextureParams {
-                dest_size: Some(Vec2::new(dest_rect.x, dest_rect.y)),
+                dest_size: Some(Vec2::new(dest_rect.w, dest_rect.h)),
                 source: Some(Rect::new(src_rect.x, src_rect.y, src_rect.w, src_rect.h)),
                 rotation: params.rotation,
                 flip_x: params.flip_x,
@@ -122,4 +185,133 @@ impl SpriteAtlas {
             },
         );
     }
+
+    pub fn draw_nine_slice(
+        &self,
+        name: &str,
+        dest: Rect,
+        params: SpriteDrawParams,
+    ) -> StoryResult<()> {
+        let sprite = self
+            .get_sprite(name)
+            .ok_or_else(|| StoryError::missing("sprite", name))?;
+        self.draw_nine_slice_definition(sprite, dest, params);
+        Ok(())
+    }
+
+    /// 九宫格绘制：四角保持原尺寸（乘以 `params.scale`），四边与中心拉伸填满 `dest`
+    /// 目标区域小于边框之和时按比例缩小边框；不支持旋转和翻转
+    pub fn draw_nine_slice_definition(
+        &self,
+        sprite: &Sprite,
+        dest: Rect,
+        params: SpriteDrawParams,
+    ) {
+        for (source, target) in sprite.nine_slice(dest, params.scale) {
+            if source.w <= 0.0 || source.h <= 0.0 || target.w <= 0.0 || target.h <= 0.0 {
+                continue;
+            }
+            draw_texture_ex(
+                &self.texture,
+                target.x,
+                target.y,
+                params.color,
+                DrawTextureParams {
+                    dest_size: Some(target.size()),
+                    source: Some(source),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn panel() -> Sprite {
+        Sprite {
+            name: "panel".to_string(),
+            x: 100.0,
+            y: 200.0,
+            width: 60.0,
+            height: 40.0,
+            border_left: 10.0,
+            border_right: 20.0,
+            border_top: 5.0,
+            border_bottom: 15.0,
+        }
+    }
+
+    #[test]
+    fn corners_keep_their_size_and_edges_stretch() {
+        let slices = panel().nine_slice(Rect::new(0.0, 0.0, 300.0, 100.0), 1.0);
+        let sizes: Vec<(f32, f32)> = slices.iter().map(|(_, dest)| (dest.w, dest.h)).collect();
+        assert_eq!(
+            sizes,
+            vec![
+                (10.0, 5.0),
+                (270.0, 5.0),
+                (20.0, 5.0),
+                (10.0, 80.0),
+                (270.0, 80.0),
+                (20.0, 80.0),
+                (10.0, 15.0),
+                (270.0, 15.0),
+                (20.0, 15.0),
+            ]
+        );
+        // 源矩形按边框切分精灵
+        assert_eq!(slices[0].0, Rect::new(100.0, 200.0, 10.0, 5.0));
+        assert_eq!(slices[4].0, Rect::new(110.0, 205.0, 30.0, 20.0));
+        assert_eq!(slices[8].0, Rect::new(140.0, 225.0, 20.0, 15.0));
+        // 目标矩形首尾相接铺满 dest
+        assert_eq!(slices[8].1.right(), 300.0);
+        assert_eq!(slices[8].1.bottom(), 100.0);
+    }
+
+    #[test]
+    fn borders_follow_scale() {
+        let slices = panel().nine_slice(Rect::new(10.0, 20.0, 300.0, 100.0), 2.0);
+        assert_eq!(slices[0].1, Rect::new(10.0, 20.0, 20.0, 10.0));
+        assert_eq!(slices[8].1, Rect::new(270.0, 90.0, 40.0, 30.0));
+        assert_eq!(slices[4].1, Rect::new(30.0, 30.0, 240.0, 60.0));
+    }
+
+    #[test]
+    fn small_dest_shrinks_borders_proportionally() {
+        // 边框宽 30、高 20，目标只有 15 x 10
+        let slices = panel().nine_slice(Rect::new(0.0, 0.0, 15.0, 10.0), 1.0);
+        assert_eq!(slices[0].1, Rect::new(0.0, 0.0, 5.0, 2.5));
+        assert_eq!(slices[2].1, Rect::new(5.0, 0.0, 10.0, 2.5));
+        assert_eq!(slices[6].1, Rect::new(0.0, 2.5, 5.0, 7.5));
+        // 中间一行一列被挤掉
+        assert_eq!(slices[4].1.w, 0.0);
+        assert_eq!(slices[4].1.h, 0.0);
+        assert!(
+            slices
+                .iter()
+                .all(|(_, dest)| dest.w >= 0.0 && dest.h >= 0.0)
+        );
+    }
+
+    #[test]
+    fn sprites_without_borders_stretch_whole() {
+        let sprite = Sprite {
+            border_left: 0.0,
+            border_right: 0.0,
+            border_top: 0.0,
+            border_bottom: 0.0,
+            ..panel()
+        };
+        let slices = sprite.nine_slice(Rect::new(0.0, 0.0, 120.0, 80.0), 1.0);
+        assert_eq!(slices[4].0, sprite.source_rect());
+        assert_eq!(slices[4].1, Rect::new(0.0, 0.0, 120.0, 80.0));
+        for (i, (_, dest)) in slices.iter().enumerate() {
+            if i != 4 {
+                assert!(dest.w == 0.0 || dest.h == 0.0);
+            }
+        }
+    }
 }
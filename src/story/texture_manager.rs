@@ -1,9 +1,10 @@
-use anyhow::Result;
 use image::ImageReader;
 use macroquad::prelude::*;
 use std::collections::HashMap;
 use std::io::Cursor;
 
+use crate::story::{StoryError, StoryResult};
+
 pub struct TextureManager {
     pub textures: HashMap<String, Texture2D>,
 }
@@ -13,15 +14,18 @@ impl TextureManager {
         &mut self,
         path: &str,
         texture_name: Option<&str>,
-    ) -> Result<()> {
+    ) -> StoryResult<()> {
+        let texture_error = |message: String| StoryError::Texture {
+            path: path.to_string(),
+            message,
+        };
         let extension = path.split('.').last().unwrap_or("").to_lowercase();
 
         match extension.as_str() {
             "png" | "bmp" | "tga" | "gif" => {
                 let texture = load_texture(path)
                     .await
-                    .map_err(|e| format!("加载失败 {}: {:?}", path, e))
-                    .unwrap();
+                    .map_err(|e| texture_error(format!("{:?}", e)))?;
                 if let Some(name) = texture_name {
                     self.textures.insert(name.to_string(), texture);
                 } else {
@@ -30,11 +34,13 @@ impl TextureManager {
                 Ok(())
             }
             "jpg" | "jpeg" | "webp" => {
-                let bytes = std::fs::read(path)?;
+                let bytes = std::fs::read(path).map_err(|e| StoryError::io(path, e))?;
 
                 let img = ImageReader::new(Cursor::new(&bytes))
-                    .with_guessed_format()?
-                    .decode()?
+                    .with_guessed_format()
+                    .map_err(|e| StoryError::io(path, e))?
+                    .decode()
+                    .map_err(|e| texture_error(e.to_string()))?
                     .to_rgba8();
 
                 let (width, height) = img.dimensions();
@@ -46,7 +52,7 @@ impl TextureManager {
                 }
                 Ok(())
             }
-            _ => Err(anyhow::anyhow!("不支持的格式: .{}", extension)),
+            _ => Err(texture_error(format!("不支持的格式: .{}", extension))),
         }
     }
 }
@@ -60,4 +66,23 @@ impl TextureManager {
     pub fn get(&self, path: &str) -> Option<&Texture2D> {
         self.textures.get(path)
     }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.textures.contains_key(path)
+    }
+
+    /// 移出纹理，没有其他引用时显存随之释放
+    pub fn remove(&mut self, path: &str) -> Option<Texture2D> {
+        self.textures.remove(path)
+    }
+
+    /// 纹理按 RGBA8 估算的占用字节数
+    pub fn memory(&self, path: &str) -> usize {
+        self.textures.get(path).map_or(0, texture_memory)
+    }
+}
+
+/// 纹理按 RGBA8 估算的占用字节数
+pub fn texture_memory(texture: &Texture2D) -> usize {
+    texture.width() as usize * texture.height() as usize * 4
 }
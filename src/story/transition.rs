@@ -0,0 +1,113 @@
+use macroquad::prelude::*;
+
+use crate::story::BackgroundView;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransitionKind {
+    /// 先暗到全黑再亮起
+    Fade,
+    /// 新旧背景交叉淡化
+    Dissolve,
+    /// 新背景从左向右擦入
+    Wipe,
+    /// 白色闪光，在最亮时切换背景
+    Flash,
+}
+
+impl TransitionKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "fade" => Some(Self::Fade),
+            "dissolve" => Some(Self::Dissolve),
+            "wipe" => Some(Self::Wipe),
+            "flash" => Some(Self::Flash),
+            _ => None,
+        }
+    }
+
+    pub fn default_duration(&self) -> f32 {
+        match self {
+            Self::Fade => 1.0,
+            Self::Dissolve | Self::Wipe => 0.8,
+            Self::Flash => 0.5,
+        }
+    }
+}
+
+/// 脚本中的转场参数，例如 `# bg: BG_X with=dissolve time=1.2`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransitionSpec {
+    pub kind: TransitionKind,
+    pub duration: f32,
+}
+
+/// 进行中的转场，期间屏蔽推进输入，点击可直接结束
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub spec: TransitionSpec,
+    /// 转场前的背景，`None` 表示纯色
+    pub from: Option<String>,
+    pub from_view: BackgroundView,
+    pub elapsed: f32,
+}
+
+impl Transition {
+    pub fn new(spec: TransitionSpec, from: Option<String>, from_view: BackgroundView) -> Self {
+        Self {
+            spec,
+            from,
+            from_view,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.elapsed += delta_time;
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.spec.duration <= 0.0 {
+            1.0
+        } else {
+            (self.elapsed / self.spec.duration).clamp(0.0, 1.0)
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress() >= 1.0
+    }
+
+    pub fn finish(&mut self) {
+        self.elapsed = self.spec.duration;
+    }
+
+    /// 画面此刻是否仍显示旧背景
+    pub fn shows_old_background(&self) -> bool {
+        match self.spec.kind {
+            TransitionKind::Fade => self.progress() < 0.5,
+            TransitionKind::Flash => self.progress() < FLASH_PEAK,
+            TransitionKind::Dissolve | TransitionKind::Wipe => false,
+        }
+    }
+
+    /// 覆盖全屏的遮罩颜色，只有黑场和闪白有遮罩
+    pub fn overlay(&self) -> Option<Color> {
+        let t = self.progress();
+        match self.spec.kind {
+            TransitionKind::Fade => Some(Color::new(0.0, 0.0, 0.0, 1.0 - (2.0 * t - 1.0).abs())),
+            // 快速变白，缓慢褪去
+            TransitionKind::Flash => {
+                let alpha = if t < FLASH_PEAK {
+                    t / FLASH_PEAK
+                } else {
+                    1.0 - (t - FLASH_PEAK) / (1.0 - FLASH_PEAK)
+                };
+                Some(Color::new(1.0, 1.0, 1.0, alpha))
+            }
+            TransitionKind::Dissolve | TransitionKind::Wipe => None,
+        }
+    }
+}
+
+/// 闪白达到最亮的进度
+const FLASH_PEAK: f32 = 0.2;
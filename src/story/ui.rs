@@ -1,5 +1,18 @@
 use macroquad::prelude::*;
 
+use crate::story::sprite::{SpriteAtlas, SpriteDrawParams};
+use crate::story::{
+    BackgroundView, ChoiceMenu, ErrorLog, History, LoadProgress, PlayMode, Playback, SaveMenu,
+    SaveMenuMode, VIRTUAL_HEIGHT, VIRTUAL_WIDTH, action_mask, begin_canvas, canvas_mouse_position,
+    end_canvas,
+};
+
+/// 括号中动作描写的文字颜色
+const ACTION_COLOR: Color = Color::new(0.65, 0.75, 0.9, 1.0);
+
+/// 没有选项框纹理时，选项底板使用的图集九宫格精灵
+const CHOICE_SLICE: &str = "Common_Bg_Raius10px";
+
 pub fn draw_dialog_box(
     gradient_height: f32,
     speaker: Option<&String>,
@@ -8,8 +21,8 @@ pub fn draw_dialog_box(
 ) {
     draw_vertical_gradient(
         0.0,
-        screen_height() - gradient_height - gradient_height / 4.0,
-        screen_width(),
+        VIRTUAL_HEIGHT - gradient_height - gradient_height / 4.0,
+        VIRTUAL_WIDTH,
         gradient_height + gradient_height / 4.0,
         Color::new(0.05, 0.05, 0.1, 0.8),
         Color::new(0.05, 0.05, 0.1, 0.8),
@@ -17,8 +30,8 @@ pub fn draw_dialog_box(
 
     draw_vertical_gradient(
         0.0,
-        screen_height() - gradient_height - gradient_height / 4.0 - gradient_height / 2.0,
-        screen_width(),
+        VIRTUAL_HEIGHT - gradient_height - gradient_height / 4.0 - gradient_height / 2.0,
+        VIRTUAL_WIDTH,
         gradient_height / 2.0,
         Color::new(0.05, 0.05, 0.1, 0.0),
         Color::new(0.05, 0.05, 0.1, 0.8),
@@ -26,21 +39,21 @@ pub fn draw_dialog_box(
 
     draw_line(
         120.0,
-        screen_height() - gradient_height / 2.0 - gradient_height / 4.0 - gradient_height / 8.0,
-        screen_width() - 120.0,
-        screen_height() - gradient_height / 2.0 - gradient_height / 4.0 - gradient_height / 8.0,
+        VIRTUAL_HEIGHT - gradient_height / 2.0 - gradient_height / 4.0 - gradient_height / 8.0,
+        VIRTUAL_WIDTH - 120.0,
+        VIRTUAL_HEIGHT - gradient_height / 2.0 - gradient_height / 4.0 - gradient_height / 8.0,
         2.0,
         GRAY,
     );
     let text_x = 120.0;
-    let text_y = screen_height() - gradient_height / 2.0 - gradient_height / 6.0;
-    let max_text_width = screen_width() - 240.0; // 屏幕宽度减去左右边距
+    let text_y = VIRTUAL_HEIGHT - gradient_height / 2.0 - gradient_height / 6.0;
+    let max_text_width = VIRTUAL_WIDTH - 240.0; // 屏幕宽度减去左右边距
     let font_size = 32.0;
     if let Some(speaker_name) = speaker {
         draw_text_ex(
             speaker_name,
             120.0,
-            screen_height() - gradient_height,
+            VIRTUAL_HEIGHT - gradient_height,
             TextParams {
                 font: font,      // 指定字体
                 font_size: 48,   // 字体大小
@@ -51,77 +64,483 @@ pub fn draw_dialog_box(
             },
         );
     }
-    // 绘制带自动换行的文本
-    draw_text_wrapped(
-        current_text,
+    // 绘制带自动换行的文本，动作描写换色显示
+    draw_dialogue_lines(
+        &wrap_text(current_text, font_size, max_text_width, font),
         text_x,
         text_y,
-        font_size,
-        max_text_width,
-        WHITE,
-        font,
+        font_size * 1.2,
+        TextParams {
+            font,
+            font_size: font_size as u16,
+            color: WHITE,
+            ..Default::default()
+        },
     );
 }
 
+/// 逐行绘制已换行的台词，括号中的动作描写用 [`ACTION_COLOR`] 绘制
+pub fn draw_dialogue_lines(lines: &[String], x: f32, y: f32, line_height: f32, params: TextParams) {
+    let mask = action_mask(&lines.concat());
+    let mut index = 0;
+    let mut current_y = y;
+    for line in lines {
+        let chars: Vec<char> = line.chars().collect();
+        let mut current_x = x;
+        let mut start = 0;
+        // 连续同色的字符合并成一段绘制
+        while start < chars.len() {
+            let is_action = mask[index + start];
+            let end = (start..chars.len())
+                .find(|&i| mask[index + i] != is_action)
+                .unwrap_or(chars.len());
+            let run: String = chars[start..end].iter().collect();
+            let color = if is_action {
+                ACTION_COLOR
+            } else {
+                params.color
+            };
+            let dimensions = draw_text_ex(
+                &run,
+                current_x,
+                current_y,
+                TextParams {
+                    color,
+                    ..params.clone()
+                },
+            );
+            current_x += dimensions.width;
+            start = end;
+        }
+        index += chars.len();
+        current_y += line_height;
+    }
+}
+
 pub fn draw_chioce(
-    current_choices: &Vec<inkling::Choice>,
-    texture: &Texture2D,
+    current_choices: &[inkling::Choice],
+    menu: &ChoiceMenu,
+    texture: Option<&Texture2D>,
+    atlas: Option<&SpriteAtlas>,
     material: Option<&Material>,
     font: Option<&Font>,
 ) {
-    for (i, choice) in current_choices.iter().enumerate() {
-        let y_pos = screen_height() / 3.0 + (i as f32 * 84.0);
-        if let Some(material) = material {
-            gl_use_material(material);
+    let rects = ChoiceMenu::layout(current_choices.len(), VIRTUAL_WIDTH, VIRTUAL_HEIGHT);
+    for (i, (choice, rect)) in current_choices.iter().zip(rects).enumerate() {
+        let progress = menu.appear_progress(i);
+        if progress <= 0.0 {
+            continue;
         }
-        draw_texture_ex(
-            texture,
-            screen_width() * 0.1 + (i as f32 * 16.),
-            y_pos - 34.,
-            WHITE,
-            DrawTextureParams {
-                dest_size: Some(Vec2 {
-                    x: screen_width() * 0.9,
-                    y: screen_height() * 0.08,
-                }),
-                ..Default::default()
-            },
+        // 出场时从右侧滑入，悬停放大，按下缩小
+        let scale = if menu.pressed == Some(i) {
+            0.97
+        } else if menu.focused == Some(i) {
+            1.03
+        } else {
+            1.0
+        };
+        let offset = (1.0 - progress) * VIRTUAL_WIDTH * 0.15;
+        let rect = Rect::new(
+            rect.x + offset + rect.w * (1.0 - scale) / 2.0,
+            rect.y + rect.h * (1.0 - scale) / 2.0,
+            rect.w * scale,
+            rect.h * scale,
         );
-        gl_use_default_material();
+
+        match texture {
+            Some(texture) => {
+                if let Some(material) = material {
+                    gl_use_material(material);
+                }
+                draw_texture_ex(
+                    texture,
+                    rect.x,
+                    rect.y,
+                    WHITE,
+                    DrawTextureParams {
+                        dest_size: Some(rect.size()),
+                        ..Default::default()
+                    },
+                );
+                gl_use_default_material();
+            }
+            None => {
+                let color = Color::new(0.95, 0.97, 1.0, 0.9);
+                let sliced = atlas.is_some_and(|atlas| {
+                    atlas
+                        .draw_nine_slice(
+                            CHOICE_SLICE,
+                            rect,
+                            SpriteDrawParams {
+                                color,
+                                ..SpriteDrawParams::new()
+                            },
+                        )
+                        .is_ok()
+                });
+                if !sliced {
+                    draw_rectangle(rect.x, rect.y, rect.w, rect.h, color);
+                }
+            }
+        }
+
+        if menu.pressed == Some(i) {
+            draw_rectangle(
+                rect.x,
+                rect.y,
+                rect.w,
+                rect.h,
+                Color::new(0.0, 0.0, 0.0, 0.15),
+            );
+        } else if menu.focused == Some(i) {
+            draw_rectangle(
+                rect.x,
+                rect.y,
+                rect.w,
+                rect.h,
+                Color::new(1.0, 1.0, 1.0, 0.15),
+            );
+            draw_rectangle_lines(
+                rect.x,
+                rect.y,
+                rect.w,
+                rect.h,
+                3.0,
+                Color::new(0.55, 0.8, 1.0, 1.0),
+            );
+        }
 
         // 选项文本
         let text = format!("{}. {}", i + 1, choice.text);
+        let font_size = (rect.h * 0.45).clamp(20.0, 36.0);
 
         draw_text_center(
             &text,
-            screen_width() / 2.,
-            y_pos + 2.,
+            rect.x + rect.w / 2.,
+            rect.y + rect.h / 2. + font_size / 3.,
             font,  // 指定字体
             BLACK, // 颜色
-            30.,   // 字体大小
+            font_size,
         );
+    }
+}
+
+const ERROR_FONT_SIZE: f32 = 24.0;
+const ERROR_MARGIN: f32 = 120.0;
+const ERROR_PADDING: f32 = 24.0;
+
+/// 错误提示框的位置与换行后的内容，绘制与点击检测共用
+pub fn error_overlay_layout(errors: &ErrorLog, font: Option<&Font>) -> (Rect, Vec<String>) {
+    let max_width = VIRTUAL_WIDTH - (ERROR_MARGIN + ERROR_PADDING) * 2.0;
+    let lines: Vec<String> = errors
+        .entries()
+        .iter()
+        .flat_map(|entry| wrap_text(entry, ERROR_FONT_SIZE, max_width, font))
+        .collect();
+    // 标题、各行错误与底部提示
+    let height = ERROR_PADDING * 2.0 + 40.0 + (lines.len() as f32 + 1.5) * ERROR_FONT_SIZE * 1.2;
+    (
+        Rect::new(
+            ERROR_MARGIN,
+            40.0,
+            VIRTUAL_WIDTH - ERROR_MARGIN * 2.0,
+            height,
+        ),
+        lines,
+    )
+}
 
-        // 鼠标悬停效果
-        // let mouse_pos = mouse_position();
-        // let rect = Rect::new(60.0, y_pos - 48.0, 700.0, 64.0);
-        // if rect.contains(vec2(mouse_pos.0, mouse_pos.1)) {
-        //     draw_rectangle_lines(55.0, y_pos - 50.0, 710.0, 64.0, 2.0, WHITE);
-        // }
+/// 在画面上方列出错误，点击提示框或按 Esc 关闭
+pub fn draw_error_overlay(errors: &ErrorLog, font: Option<&Font>) {
+    let (rect, lines) = error_overlay_layout(errors, font);
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.2, 0.03, 0.05, 0.9),
+    );
+    draw_rectangle_lines(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        2.0,
+        Color::new(1.0, 0.4, 0.4, 1.0),
+    );
+    let x = rect.x + ERROR_PADDING;
+    let mut y = rect.y + ERROR_PADDING + 28.0;
+    draw_text_ex(
+        "ERROR",
+        x,
+        y,
+        TextParams {
+            font,
+            font_size: 32,
+            color: Color::new(1.0, 0.5, 0.5, 1.0),
+            ..Default::default()
+        },
+    );
+    y += 40.0;
+    let params = TextParams {
+        font,
+        font_size: ERROR_FONT_SIZE as u16,
+        color: WHITE,
+        ..Default::default()
+    };
+    for line in &lines {
+        draw_text_ex(line, x, y, params.clone());
+        y += ERROR_FONT_SIZE * 1.2;
     }
+    draw_text_ex(
+        "点击此处或按 Esc 关闭",
+        x,
+        y + ERROR_FONT_SIZE * 0.5,
+        TextParams {
+            color: GRAY,
+            ..params
+        },
+    );
 }
 
-pub fn draw_background(texture: &Texture2D) {
-    let screen_width = screen_width();
-    let screen_height = screen_height();
+const SAVE_ROW_WIDTH: f32 = 1100.0;
+const SAVE_ROW_HEIGHT: f32 = 72.0;
+const SAVE_ROW_SPACING: f32 = 14.0;
 
-    // 3. 绘制图片，尺寸设置为整个窗口
-    draw_texture_ex(
-        texture,
-        0.0, // 从左上角(0,0)开始绘制
+/// 存档菜单中每个槽位一行，绘制与点击检测共用
+pub fn save_menu_layout(count: usize) -> Vec<Rect> {
+    let x = (VIRTUAL_WIDTH - SAVE_ROW_WIDTH) / 2.0;
+    let total = count as f32 * (SAVE_ROW_HEIGHT + SAVE_ROW_SPACING) - SAVE_ROW_SPACING;
+    let top = (VIRTUAL_HEIGHT - total) / 2.0 + 30.0;
+    (0..count)
+        .map(|i| {
+            Rect::new(
+                x,
+                top + i as f32 * (SAVE_ROW_HEIGHT + SAVE_ROW_SPACING),
+                SAVE_ROW_WIDTH,
+                SAVE_ROW_HEIGHT,
+            )
+        })
+        .collect()
+}
+
+/// 绘制存档/读档菜单：槽位编号、存档所在的 knot 与最后一句台词
+pub fn draw_save_menu(menu: &SaveMenu, font: Option<&Font>) {
+    let Some(mode) = menu.mode else {
+        return;
+    };
+    let accent = Color::new(0.55, 0.8, 1.0, 1.0);
+    draw_rectangle(
+        0.0,
         0.0,
+        VIRTUAL_WIDTH,
+        VIRTUAL_HEIGHT,
+        Color::new(0.02, 0.02, 0.06, 0.85),
+    );
+    let title = match mode {
+        SaveMenuMode::Save => "SAVE",
+        SaveMenuMode::Load => "LOAD",
+    };
+    draw_text_center(title, VIRTUAL_WIDTH / 2., 60.0, font, accent, 36.);
+
+    let mouse = canvas_mouse_position();
+    let rects = save_menu_layout(menu.slots.len());
+    for (slot, (rect, meta)) in rects.iter().zip(&menu.slots).enumerate() {
+        let active = slot == menu.focused || rect.contains(mouse);
+        let fill = if active { 0.18 } else { 0.08 };
+        draw_rectangle(
+            rect.x,
+            rect.y,
+            rect.w,
+            rect.h,
+            Color::new(1.0, 1.0, 1.0, fill),
+        );
+        if active {
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2.0, accent);
+        }
+        let params = TextParams {
+            font,
+            font_size: 28,
+            color: WHITE,
+            ..Default::default()
+        };
+        let baseline = rect.y + rect.h / 2.0 + 10.0;
+        draw_text_ex(
+            &format!("{}", slot + 1),
+            rect.x + 24.0,
+            baseline,
+            TextParams {
+                color: accent,
+                ..params.clone()
+            },
+        );
+        let Some(meta) = meta else {
+            draw_text_ex(
+                "空",
+                rect.x + 90.0,
+                baseline,
+                TextParams {
+                    color: GRAY,
+                    ..params
+                },
+            );
+            continue;
+        };
+        let knot = match &meta.stitch {
+            Some(stitch) => format!("{}.{}", meta.knot, stitch),
+            None => meta.knot.clone(),
+        };
+        draw_text_ex(
+            &knot,
+            rect.x + 90.0,
+            baseline,
+            TextParams {
+                color: GRAY,
+                font_size: 22,
+                ..params.clone()
+            },
+        );
+        let preview = wrap_text(&meta.preview, 28.0, rect.w - 400.0, font);
+        if let Some(first) = preview.first() {
+            let ellipsis = if preview.len() > 1 { "…" } else { "" };
+            draw_text_ex(
+                &format!("{}{}", first, ellipsis),
+                rect.x + 340.0,
+                baseline,
+                params,
+            );
+        }
+    }
+    draw_text_center(
+        "数字键或点击选择槽位，Esc 返回",
+        VIRTUAL_WIDTH / 2.,
+        VIRTUAL_HEIGHT - 40.0,
+        font,
+        GRAY,
+        24.,
+    );
+}
+
+/// 载入画面：旋转的圆点、当前资源名、进度条与成功/失败数量
+pub fn draw_loading_screen(progress: &LoadProgress) {
+    let font = progress.font.as_ref();
+    let accent = Color::new(0.55, 0.8, 1.0, 1.0);
+    begin_canvas();
+
+    // 八个圆点依次变亮，表示仍在载入
+    let center = vec2(VIRTUAL_WIDTH / 2.0, VIRTUAL_HEIGHT / 2.0 - 120.0);
+    let head = (get_time() * 8.0) as usize % 8;
+    for i in 0..8 {
+        let angle = i as f32 / 8.0 * std::f32::consts::TAU;
+        let alpha = 1.0 - ((head + 8 - i) % 8) as f32 / 8.0;
+        draw_circle(
+            center.x + angle.cos() * 32.0,
+            center.y + angle.sin() * 32.0,
+            6.0,
+            Color::new(accent.r, accent.g, accent.b, alpha),
+        );
+    }
+
+    draw_text_center(
+        &format!("Loading {}", progress.current),
+        VIRTUAL_WIDTH / 2.0,
+        VIRTUAL_HEIGHT / 2.0,
+        font,
         WHITE,
+        32.0,
+    );
+
+    let bar = Rect::new(
+        VIRTUAL_WIDTH * 0.25,
+        VIRTUAL_HEIGHT / 2.0 + 40.0,
+        VIRTUAL_WIDTH * 0.5,
+        12.0,
+    );
+    draw_rectangle(bar.x, bar.y, bar.w, bar.h, Color::new(1.0, 1.0, 1.0, 0.15));
+    draw_rectangle(bar.x, bar.y, bar.w * progress.ratio(), bar.h, accent);
+
+    let mut counts = format!("{} / {}", progress.loaded + progress.failed, progress.total);
+    if progress.failed > 0 {
+        counts.push_str(&format!("  失败 {}", progress.failed));
+    }
+    draw_text_center(&counts, VIRTUAL_WIDTH / 2.0, bar.y + 60.0, font, GRAY, 24.0);
+    end_canvas();
+}
+
+/// 载入失败、无法进入游戏时的全屏错误信息
+pub fn draw_fatal_error(message: &str, font: Option<&Font>) {
+    begin_canvas();
+    draw_text_center(
+        "启动失败",
+        VIRTUAL_WIDTH / 2.0,
+        VIRTUAL_HEIGHT / 3.0,
+        font,
+        Color::new(1.0, 0.5, 0.5, 1.0),
+        48.0,
+    );
+    draw_text_wrapped(
+        message,
+        ERROR_MARGIN,
+        VIRTUAL_HEIGHT / 3.0 + 80.0,
+        28.0,
+        VIRTUAL_WIDTH - ERROR_MARGIN * 2.0,
+        WHITE,
+        font,
+    );
+    draw_text_center(
+        "按 Esc 退出",
+        VIRTUAL_WIDTH / 2.0,
+        VIRTUAL_HEIGHT - 80.0,
+        font,
+        GRAY,
+        28.0,
+    );
+    end_canvas();
+}
+
+/// 在右上角显示 AUTO / SKIP 状态
+pub fn draw_play_mode(playback: &Playback, font: Option<&Font>) {
+    let text = if playback.is_skipping() {
+        "SKIP ▶▶"
+    } else if playback.mode == PlayMode::Auto {
+        "AUTO ▶"
+    } else {
+        return;
+    };
+    let width = measure_text(text, font, 28, 1.0).width;
+    draw_text_ex(
+        text,
+        VIRTUAL_WIDTH - width - 40.0,
+        56.0,
+        TextParams {
+            font,
+            font_size: 28,
+            color: Color::new(0.55, 0.8, 1.0, 1.0),
+            ..Default::default()
+        },
+    );
+}
+
+/// 按显示方式绘制背景，只绘制屏幕左侧 `visible` (0.0 - 1.0) 比例的部分，用于擦除转场
+pub fn draw_background(texture: &Texture2D, view: &BackgroundView, color: Color, visible: f32) {
+    let dest = view.dest_rect(texture.size(), vec2(VIRTUAL_WIDTH, VIRTUAL_HEIGHT));
+    let width = (VIRTUAL_WIDTH * visible.clamp(0.0, 1.0) - dest.x).clamp(0.0, dest.w);
+    if width <= 0.0 {
+        return;
+    }
+    draw_texture_ex(
+        texture,
+        dest.x,
+        dest.y,
+        color,
         DrawTextureParams {
-            dest_size: Some(Vec2::new(screen_width, screen_height)), // 关键：设置目标尺寸为全屏
+            dest_size: Some(Vec2::new(width, dest.h)),
+            source: Some(Rect::new(
+                0.0,
+                0.0,
+                texture.width() * width / dest.w,
+                texture.height(),
+            )),
             ..Default::default()
         },
     );
@@ -195,23 +614,22 @@ pub fn draw_text_wrapped(
     };
     // 初始行位置
     let mut current_y = y;
+    for line in wrap_text(text, font_size, max_width, font) {
+        draw_text_ex(line.as_str(), x, current_y, text_param.clone());
+        current_y += font_size * 1.2; // 行高，通常是字体大小的1.2倍
+    }
+}
+
+/// 按最大宽度将文本拆分为多行
+pub fn wrap_text(text: &str, font_size: f32, max_width: f32, font: Option<&Font>) -> Vec<String> {
+    let mut lines = Vec::new();
     // 当前行累积的文本
     let mut current_line = String::new();
     // 当前行已使用的宽度
     let mut current_width = 0.0;
 
-    // 按单词分割（英文以空格分隔，中文等通常每个字符都是“单词”）
-    // 这是一个简单的实现，对于中英文混合文本，你可能需要更复杂的分词
-    // let words: Vec<&str> = text.split_whitespace().collect();
-
+    // 中文等通常每个字符都是“单词”，这里逐字符测量
     for word in text.split("") {
-        // 检查当前行是否为空
-        // let word_with_space = if current_line.is_empty() {
-        //     word.to_string()
-        // } else {
-        //     format!(" {}", word)
-        // };
-
         // 计算添加这个词后的宽度
         let word_width = measure_text(word, font, font_size as u16, 1.0).width;
 
@@ -220,19 +638,148 @@ pub fn draw_text_wrapped(
             current_line.push_str(word);
             current_width += word_width;
         } else {
-            // 否则，绘制当前行，并开始新的一行
-            draw_text_ex(current_line.as_str(), x, current_y, text_param.clone());
-            current_y += font_size * 1.2; // 行高，通常是字体大小的1.2倍
-
-            // 新行以当前单词开始
+            // 否则，结束当前行，并以当前单词开始新的一行
+            lines.push(current_line);
             current_line = word.to_string();
-            current_width = measure_text(word, None, font_size as u16, 1.0).width;
+            current_width = word_width;
         }
     }
 
-    // 绘制最后一行
     if !current_line.is_empty() {
-        draw_text_ex(current_line.as_str(), x, current_y, text_param.clone());
+        lines.push(current_line);
+    }
+    lines
+}
+
+/// 回看窗口中一条记录的布局，绘制与点击检测共用
+pub struct BacklogBlock {
+    /// 在 `History::entries` 中的下标
+    pub entry: usize,
+    /// 记录顶部的 y 坐标
+    pub top: f32,
+    pub lines: Vec<String>,
+    /// 有语音时的重播按钮
+    pub voice_button: Option<Rect>,
+}
+
+const BACKLOG_MARGIN: f32 = 120.0;
+const BACKLOG_FONT_SIZE: f32 = 28.0;
+const BACKLOG_SPEAKER_HEIGHT: f32 = 36.0;
+
+/// 从最新一条向上排布，直到填满屏幕
+pub fn backlog_layout(history: &History, font: Option<&Font>) -> Vec<BacklogBlock> {
+    let line_height = BACKLOG_FONT_SIZE * 1.2;
+    let max_width = VIRTUAL_WIDTH - BACKLOG_MARGIN * 2.0;
+    let top = 100.0;
+    let mut bottom = VIRTUAL_HEIGHT - 60.0;
+    let mut blocks = Vec::new();
+
+    let entries = history.entries();
+    for (entry, item) in entries.iter().enumerate().rev().skip(history.scroll) {
+        let lines = match &item.choice {
+            Some(choice) => wrap_text(&format!("▶ {}", choice), BACKLOG_FONT_SIZE, max_width, font),
+            None => wrap_text(&item.content, BACKLOG_FONT_SIZE, max_width, font),
+        };
+        let speaker_height = if item.speaker.is_some() {
+            BACKLOG_SPEAKER_HEIGHT
+        } else {
+            0.0
+        };
+        let block_height = speaker_height + lines.len() as f32 * line_height + 24.0;
+        if bottom - block_height < top {
+            break;
+        }
+        let block_top = bottom - block_height;
+        let voice_button = item
+            .voice
+            .as_ref()
+            .map(|_| Rect::new(BACKLOG_MARGIN - 56.0, block_top + 8.0, 40.0, 32.0));
+        blocks.push(BacklogBlock {
+            entry,
+            top: block_top,
+            lines,
+            voice_button,
+        });
+        bottom -= block_height;
+    }
+    blocks
+}
+
+/// 绘制对话回看窗口，最新的记录在最下方
+pub fn draw_backlog(history: &History, font: Option<&Font>) {
+    draw_rectangle(
+        0.0,
+        0.0,
+        VIRTUAL_WIDTH,
+        VIRTUAL_HEIGHT,
+        Color::new(0.02, 0.02, 0.06, 0.85),
+    );
+    draw_text_center(
+        "LOG",
+        VIRTUAL_WIDTH / 2.,
+        60.0,
+        font,
+        Color::new(0.55, 0.8, 1.0, 1.0),
+        36.,
+    );
+
+    let line_height = BACKLOG_FONT_SIZE * 1.2;
+    let mouse = canvas_mouse_position();
+    for block in backlog_layout(history, font) {
+        let entry = &history.entries()[block.entry];
+        let color = if entry.choice.is_some() {
+            Color::new(0.55, 0.8, 1.0, 1.0)
+        } else {
+            WHITE
+        };
+        let mut y = block.top + 24.0;
+        if let Some(speaker) = entry.display_name.as_ref().or(entry.speaker.as_ref()) {
+            draw_text_ex(
+                speaker,
+                BACKLOG_MARGIN,
+                y,
+                TextParams {
+                    font,
+                    font_size: 30,
+                    color: Color::new(1.0, 0.85, 0.4, 1.0),
+                    ..Default::default()
+                },
+            );
+            y += BACKLOG_SPEAKER_HEIGHT;
+        }
+        let params = TextParams {
+            font,
+            font_size: BACKLOG_FONT_SIZE as u16,
+            color,
+            ..Default::default()
+        };
+        if entry.choice.is_some() {
+            for line in &block.lines {
+                draw_text_ex(line, BACKLOG_MARGIN, y, params.clone());
+                y += line_height;
+            }
+        } else {
+            draw_dialogue_lines(&block.lines, BACKLOG_MARGIN, y, line_height, params);
+        }
+        if let Some(button) = block.voice_button {
+            let alpha = if button.contains(mouse) { 1.0 } else { 0.6 };
+            draw_rectangle_lines(
+                button.x,
+                button.y,
+                button.w,
+                button.h,
+                2.0,
+                Color::new(0.55, 0.8, 1.0, alpha),
+            );
+            // 播放图标
+            let center = button.center();
+            draw_triangle(
+                vec2(center.x - 6.0, center.y - 8.0),
+                vec2(center.x - 6.0, center.y + 8.0),
+                vec2(center.x + 8.0, center.y),
+                Color::new(0.55, 0.8, 1.0, alpha),
+            );
+        }
     }
 }
 